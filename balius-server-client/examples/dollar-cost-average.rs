@@ -0,0 +1,38 @@
+//! Start a dollar-cost-average worker on a local balius-server, and print its signer key.
+//! This does the same thing as `dollar-cost-average.sh` at the root of the repo.
+
+use balius_server_client::{Client, types::WorkerSpec};
+use serde_json::json;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let client = Client::new("http://localhost:3000".parse()?);
+
+    let spec = WorkerSpec {
+        network: "preview".to_string(),
+        operator_version: "1".to_string(),
+        throughput_tier: "0".to_string(),
+        display_name: "My worker".to_string(),
+        url: "file:///workers/dollar-cost-average.wasm".parse()?,
+        config: json!({
+            "network": "preview",
+            "interval": 60,
+            "offer_token": ".",
+            "offer_amount": 1000000,
+            "receive_token": "99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e15.534245525259",
+            "receive_amount_min": 0,
+        }),
+        version: "1".to_string(),
+    };
+
+    let project = client.create_project("My project").await?;
+    let worker = client.create_worker(project.id, spec).await?;
+    println!("created worker {}", worker.id);
+
+    let key = client
+        .invoke_worker(&worker.id, "get-signer-key", json!({}))
+        .await?;
+    println!("{key}");
+
+    Ok(())
+}
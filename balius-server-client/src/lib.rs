@@ -0,0 +1,161 @@
+pub mod types;
+
+use std::fmt::Display;
+
+use reqwest::StatusCode;
+use serde::{Serialize, de::DeserializeOwned};
+use url::Url;
+
+use crate::types::{
+    CreateProjectRequest, CreateProjectResponse, CreateResourceRequest, CreateResourceResponse,
+    ErrorBody, InvokeWorkerRequest, ResourceKind, WorkerSpec,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The server could not be reached, or returned something other than JSON.
+    Http(reqwest::Error),
+    /// The server returned an error response.
+    Api { status: StatusCode, body: ErrorBody },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http(err) => f.write_fmt(format_args!("http error: {err}")),
+            Self::Api { status, body } => {
+                f.write_fmt(format_args!("{status} ({}): {}", body.code, body.error))
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(value: reqwest::Error) -> Self {
+        Self::Http(value)
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// A client for the balius-server HTTP API.
+///
+/// # Examples
+/// ```no_run
+/// # use balius_server_client::{Client, types::WorkerSpec};
+/// # async fn example(spec: WorkerSpec) -> balius_server_client::Result<()> {
+/// let client = Client::new("http://localhost:3000".parse().unwrap());
+/// let project = client.create_project("my project").await?;
+/// let worker = client.create_worker(&project.id, spec).await?;
+/// let key = client
+///     .invoke_worker(&worker.id, "get-signer-key", serde_json::json!({}))
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Client {
+    base_url: Url,
+    http: reqwest::Client,
+}
+
+impl Client {
+    pub fn new(base_url: Url) -> Self {
+        Self::with_http_client(base_url, reqwest::Client::new())
+    }
+
+    pub fn with_http_client(base_url: Url, http: reqwest::Client) -> Self {
+        Self { base_url, http }
+    }
+
+    pub async fn create_project(&self, name: impl Into<String>) -> Result<CreateProjectResponse> {
+        let req = CreateProjectRequest { name: name.into() };
+        self.post("projects", &req).await
+    }
+
+    pub async fn create_worker(
+        &self,
+        project_id: impl Into<String>,
+        spec: WorkerSpec,
+    ) -> Result<CreateResourceResponse> {
+        let req = CreateResourceRequest {
+            project_id: project_id.into(),
+            kind: ResourceKind::BaliusWorker,
+            spec: spec.into(),
+        };
+        self.post("resources", &req).await
+    }
+
+    /// Call one of a worker's request handlers.
+    pub async fn invoke_worker(
+        &self,
+        worker_id: &str,
+        method: impl Into<String>,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let req = InvokeWorkerRequest {
+            method: method.into(),
+            params,
+        };
+        self.post(&format!("worker/{worker_id}"), &req).await
+    }
+
+    /// Call one of a worker's request handlers which returns CBOR or a partial transaction,
+    /// and receive the raw bytes.
+    pub async fn invoke_worker_cbor(
+        &self,
+        worker_id: &str,
+        method: impl Into<String>,
+        params: serde_json::Value,
+    ) -> Result<Vec<u8>> {
+        let req = InvokeWorkerRequest {
+            method: method.into(),
+            params,
+        };
+        let res = self
+            .http
+            .post(self.url(&format!("worker/{worker_id}")))
+            .header(reqwest::header::ACCEPT, "application/cbor")
+            .json(&req)
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            return Self::parse(res).await;
+        }
+        Ok(res.bytes().await?.to_vec())
+    }
+
+    /// Fetch the OpenAPI document describing the server's API.
+    pub async fn openapi(&self) -> Result<serde_json::Value> {
+        let res = self.http.get(self.url("openapi.json")).send().await?;
+        Self::parse(res).await
+    }
+
+    async fn post<Req: Serialize, Res: DeserializeOwned>(
+        &self,
+        path: &str,
+        req: &Req,
+    ) -> Result<Res> {
+        let res = self.http.post(self.url(path)).json(req).send().await?;
+        Self::parse(res).await
+    }
+
+    async fn parse<Res: DeserializeOwned>(res: reqwest::Response) -> Result<Res> {
+        let status = res.status();
+        if status.is_success() {
+            return Ok(res.json().await?);
+        }
+        let body = res.json().await.unwrap_or_else(|_| ErrorBody {
+            code: "unknown".to_string(),
+            error: status.to_string(),
+            worker_error: None,
+        });
+        Err(Error::Api { status, body })
+    }
+
+    fn url(&self, path: &str) -> Url {
+        self.base_url.join(path).expect("paths are always valid")
+    }
+}
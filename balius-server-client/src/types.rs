@@ -0,0 +1,224 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+use utoipa::ToSchema;
+
+/// Create a new project, which groups workers together.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct CreateProjectRequest {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct CreateProjectResponse {
+    pub id: String,
+    pub name: String,
+    pub namespace: String,
+}
+
+/// Create a new resource (currently always a Balius worker) inside a project.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateResourceRequest {
+    pub project_id: String,
+    pub kind: ResourceKind,
+    /// The worker to run. Older clients send this as a JSON encoded string, which is still accepted.
+    #[schema(value_type = WorkerSpec)]
+    pub spec: ResourceSpec,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    BaliusWorker,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct CreateResourceResponse {
+    /// The id of the new worker, used to invoke it.
+    pub id: String,
+    pub name: String,
+    pub kind: ResourceKind,
+}
+
+/// The spec of a resource, either as a structured object or as a legacy JSON encoded string.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ResourceSpec {
+    Legacy(String),
+    Structured(serde_json::Value),
+}
+
+impl ResourceSpec {
+    pub fn parse(self) -> Result<WorkerSpec, serde_json::Error> {
+        match self {
+            Self::Legacy(spec) => serde_json::from_str(&spec),
+            Self::Structured(spec) => serde_json::from_value(spec),
+        }
+    }
+}
+
+impl From<WorkerSpec> for ResourceSpec {
+    fn from(value: WorkerSpec) -> Self {
+        Self::Structured(serde_json::to_value(value).expect("a worker spec is always valid JSON"))
+    }
+}
+
+/// Describes a Balius worker to run.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkerSpec {
+    pub network: String,
+    pub operator_version: String,
+    pub throughput_tier: String,
+
+    pub display_name: String,
+    /// Where to download the wasm module from. `file:///workers/<name>.wasm` refers to a
+    /// worker bundled with the server.
+    pub url: Url,
+    /// The worker's config, handed to it as-is.
+    #[schema(value_type = Object)]
+    pub config: serde_json::Value,
+    pub version: String,
+}
+
+/// Invoke one of a worker's request handlers.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct InvokeWorkerRequest {
+    pub method: String,
+    #[schema(value_type = Object)]
+    pub params: serde_json::Value,
+}
+
+/// The body of every error response.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct ErrorBody {
+    /// A stable, machine readable identifier for the error, such as `invalid_spec` or `worker_not_found`.
+    pub code: String,
+    /// A human readable description of the error.
+    pub error: String,
+    /// For `worker_handler_error`, the kind of error the worker returned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_error: Option<WorkerErrorKind>,
+}
+
+/// The kind of error a worker returned, mirroring the codes `balius_sdk::Error` is reported with.
+#[derive(Serialize, Deserialize, ToSchema, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerErrorKind {
+    Internal,
+    BadConfig,
+    BadParams,
+    Ledger,
+    Kv,
+    Sign,
+    Http,
+    /// A code this version doesn't know about.
+    #[serde(other)]
+    Unknown,
+}
+
+impl WorkerErrorKind {
+    pub fn from_code(code: u32) -> Self {
+        match code {
+            0 => Self::Internal,
+            1 => Self::BadConfig,
+            2 => Self::BadParams,
+            3 => Self::Ledger,
+            4 => Self::Kv,
+            5 => Self::Sign,
+            6 => Self::Http,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// What kind of response a worker's request handler returned. This is also sent in the
+/// `x-balius-response-kind` header of every successful invocation.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerResponseKind {
+    Acknowledge,
+    Json,
+    Cbor,
+    PartialTx,
+}
+
+impl WorkerResponseKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Acknowledge => "acknowledge",
+            Self::Json => "json",
+            Self::Cbor => "cbor",
+            Self::PartialTx => "partial_tx",
+        }
+    }
+}
+
+/// The JSON body of an `acknowledge` response.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct AcknowledgeResponse {
+    pub kind: WorkerResponseKind,
+}
+
+/// The JSON body of a `json` response.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct JsonResponse {
+    pub kind: WorkerResponseKind,
+    /// The value the worker returned, as is.
+    #[schema(value_type = Object)]
+    pub value: serde_json::Value,
+}
+
+/// The JSON body of a `cbor` response. Request `application/cbor` to receive the raw bytes instead.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct CborResponse {
+    pub kind: WorkerResponseKind,
+    /// The hex encoded CBOR.
+    pub cbor: String,
+}
+
+/// The JSON body of a `partial_tx` response. Request `application/cbor` to receive the raw bytes instead.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct PartialTxResponse {
+    pub kind: WorkerResponseKind,
+    /// The hex encoded transaction CBOR.
+    pub tx: String,
+    /// A decoded summary of the transaction, if it could be decoded.
+    pub summary: Option<TxSummary>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct TxSummary {
+    pub hash: String,
+    /// Inputs, formatted as `txHash#index`.
+    pub inputs: Vec<String>,
+    pub outputs: Vec<TxOutputSummary>,
+    pub fee: Option<u64>,
+    /// Hex encoded key hashes which must sign the transaction.
+    pub required_signers: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct TxOutputSummary {
+    /// The bech32 encoded address, or hex if it could not be encoded.
+    pub address: String,
+    pub lovelace: u64,
+    pub assets: Vec<AssetAmount>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct AssetAmount {
+    pub policy_id: String,
+    pub asset_name: String,
+    pub amount: u64,
+}
+
+/// A structured event emitted by a strategy worker, sent as a server-sent event named `strategy`.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone)]
+pub struct WorkerEvent {
+    pub worker_id: String,
+    /// The project the worker was created in, if it was created through the API.
+    pub project_id: Option<String>,
+    /// The event itself, as emitted by `sundae_strategies::events`. It is tagged with a `type`.
+    #[schema(value_type = Object)]
+    pub event: serde_json::Value,
+}
@@ -1,24 +1,65 @@
 use std::{collections::HashMap, path::PathBuf};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use clap::Parser;
 use figment::{
     Figment,
-    providers::{Format, Yaml},
+    providers::{Format, Toml, Yaml},
+    value::magic::RelativePathBuf,
 };
 use serde::Deserialize;
 
-#[derive(Parser)]
+#[derive(Parser, Clone)]
 pub struct Args {
+    /// Config files to load, in order. Files ending in `.toml` are read as
+    /// baliusd-style manifests, everything else as YAML.
     #[clap(short, long)]
     config: Vec<PathBuf>,
 }
 
-#[derive(Deserialize, Clone)]
+/// Top level config sections we understand.
+const SECTIONS: &[&str] = &[
+    "port",
+    "data_dir",
+    "utxorpc",
+    "ledger",
+    "chainsync",
+    "workers",
+    "signing",
+];
+
+/// Sections of a baliusd manifest which only configure baliusd itself. They're allowed, so a
+/// `baliusd.toml` can be loaded as is, but have no effect.
+const BALIUSD_ONLY_SECTIONS: &[&str] =
+    &["store", "rpc", "metrics", "logging", "kv", "http", "logger"];
+
+#[derive(Clone)]
 pub struct AppConfig {
     pub port: u16,
     pub data_dir: PathBuf,
-    pub utxorpc: UtxorpcConfig,
+    /// The UTxO RPC endpoint workers query the ledger through.
+    pub ledger: UtxorpcConfig,
+    /// The UTxO RPC endpoint workers follow the chain through.
+    pub chainsync: UtxorpcConfig,
+    /// Workers to register on boot, using the same `[[workers]]` format as baliusd.
+    pub workers: Vec<StaticWorkerConfig>,
+    pub signing: SigningConfig,
+}
+
+#[derive(Deserialize)]
+struct RawConfig {
+    port: u16,
+    data_dir: PathBuf,
+    /// Used for both the ledger and chainsync, unless they're set separately.
+    utxorpc: Option<UtxorpcConfig>,
+    /// baliusd's `[ledger]` section.
+    ledger: Option<UtxorpcConfig>,
+    /// baliusd's `[chainsync]` section.
+    chainsync: Option<UtxorpcConfig>,
+    #[serde(default)]
+    workers: Vec<StaticWorkerConfig>,
+    #[serde(default)]
+    signing: SigningConfig,
 }
 
 #[derive(Deserialize, Clone)]
@@ -27,13 +68,75 @@ pub struct UtxorpcConfig {
     pub headers: Option<HashMap<String, String>>,
 }
 
+/// A worker declared in config rather than created through `POST /resources`.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct StaticWorkerConfig {
+    /// The worker's name; also used to look up its keys in `signing.keys`. The worker is served
+    /// under the id `static-{name}`, so it never collides with workers created through the API.
+    pub name: String,
+    /// Path to the compiled wasm module, relative to the file which declared it.
+    pub module: RelativePathBuf,
+    /// Path to a JSON config file for the worker, relative to the file which declared it.
+    pub config: Option<RelativePathBuf>,
+    /// The network the worker runs on, such as `"preview"`. This is set as `network` in the
+    /// worker's config, replacing any network in the config file.
+    pub network: Option<serde_json::Value>,
+}
+
+/// The id a static worker is registered and stored under.
+pub fn static_worker_id(name: &str) -> String {
+    format!("static-{name}")
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct SigningConfig {
+    #[serde(default)]
+    pub keys: Vec<StaticKeyConfig>,
+}
+
+/// Hardcoded key material for a worker, matching baliusd's `[[signing.keys]]`.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct StaticKeyConfig {
+    pub worker: String,
+    pub name: String,
+    pub algorithm: String,
+    pub private_key: String,
+}
+
 impl AppConfig {
     pub fn load(args: Args) -> Result<Self> {
         let mut figment = Figment::new().merge(Yaml::string(include_str!("../config.base.yaml")));
         for config_file in args.config {
-            figment = figment.merge(Yaml::file(config_file))
+            if config_file.extension().is_some_and(|ext| ext == "toml") {
+                figment = figment.merge(Toml::file(config_file))
+            } else {
+                figment = figment.merge(Yaml::file(config_file))
+            }
+        }
+
+        let sections: figment::value::Dict = figment.extract()?;
+        for section in sections.keys() {
+            if !SECTIONS.contains(&section.as_str())
+                && !BALIUSD_ONLY_SECTIONS.contains(&section.as_str())
+            {
+                bail!("unknown config section `{section}`");
+            }
         }
 
-        Ok(figment.extract()?)
+        let raw: RawConfig = figment.extract()?;
+        let (Some(ledger), Some(chainsync)) = (
+            raw.ledger.or_else(|| raw.utxorpc.clone()),
+            raw.chainsync.or(raw.utxorpc),
+        ) else {
+            bail!("no UTxO RPC endpoint is configured; set `utxorpc`, or `ledger` and `chainsync`");
+        };
+        Ok(Self {
+            port: raw.port,
+            data_dir: raw.data_dir,
+            ledger,
+            chainsync,
+            workers: raw.workers,
+            signing: raw.signing,
+        })
     }
 }
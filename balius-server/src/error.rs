@@ -2,59 +2,147 @@ use std::fmt::Display;
 
 use axum::{
     Json,
+    extract::{FromRequest, rejection::JsonRejection},
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use serde::Serialize;
+use balius_server_client::types::{ErrorBody, WorkerErrorKind};
 
 #[derive(Debug, PartialEq, Eq)]
-pub struct ApiError {
-    status: StatusCode,
-    message: String,
+pub enum ApiError {
+    /// The request body could not be parsed.
+    InvalidRequest(String),
+    /// The `spec` of a resource could not be parsed.
+    InvalidSpec(String),
+    ProjectNotFound(String),
+    WorkerNotFound(String),
+    /// The worker has no request handler for the given method.
+    MethodNotFound(String),
+    /// A predefined worker module does not exist.
+    ModuleNotFound(String),
+    /// A worker module could not be downloaded or compiled.
+    ModuleFetchFailed(String),
+    /// The UTxO RPC ledger could not be reached.
+    LedgerUnavailable(String),
+    /// The worker itself returned an error while handling a request.
+    WorkerHandler {
+        kind: WorkerErrorKind,
+        message: String,
+    },
+    Internal(String),
 }
 
 impl ApiError {
-    pub fn new(status: impl Into<StatusCode>, message: impl Into<String>) -> Self {
-        Self {
-            status: status.into(),
-            message: message.into(),
+    pub fn internal(err: impl Into<anyhow::Error>) -> Self {
+        Self::Internal(format!("{:#}", err.into()))
+    }
+
+    pub fn invalid_spec(err: impl Display) -> Self {
+        Self::InvalidSpec(err.to_string())
+    }
+
+    /// A stable, machine readable identifier for this error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest(_) => "invalid_request",
+            Self::InvalidSpec(_) => "invalid_spec",
+            Self::ProjectNotFound(_) => "project_not_found",
+            Self::WorkerNotFound(_) => "worker_not_found",
+            Self::MethodNotFound(_) => "method_not_found",
+            Self::ModuleNotFound(_) => "module_not_found",
+            Self::ModuleFetchFailed(_) => "module_fetch_failed",
+            Self::LedgerUnavailable(_) => "ledger_unavailable",
+            Self::WorkerHandler { .. } => "worker_handler_error",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidRequest(_) | Self::InvalidSpec(_) => StatusCode::BAD_REQUEST,
+            Self::ProjectNotFound(_)
+            | Self::WorkerNotFound(_)
+            | Self::MethodNotFound(_)
+            | Self::ModuleNotFound(_) => StatusCode::NOT_FOUND,
+            Self::ModuleFetchFailed(_) => StatusCode::BAD_GATEWAY,
+            Self::LedgerUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::WorkerHandler { kind, .. } => match kind {
+                WorkerErrorKind::BadConfig | WorkerErrorKind::BadParams => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
-}
 
-#[derive(Serialize)]
-struct ErrorResponse {
-    error: String,
+    fn message(&self) -> String {
+        match self {
+            Self::InvalidRequest(message)
+            | Self::InvalidSpec(message)
+            | Self::ModuleFetchFailed(message)
+            | Self::LedgerUnavailable(message)
+            | Self::WorkerHandler { message, .. }
+            | Self::Internal(message) => message.clone(),
+            Self::ProjectNotFound(id) => format!("project {id} not found"),
+            Self::WorkerNotFound(id) => format!("worker {id} not found"),
+            Self::MethodNotFound(method) => format!("worker has no handler for {method}"),
+            Self::ModuleNotFound(module) => format!("worker {module} not found"),
+        }
+    }
 }
 
 impl Display for ApiError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("{}: {}", self.status, self.message))
+        f.write_fmt(format_args!(
+            "{} ({}): {}",
+            self.status(),
+            self.code(),
+            self.message()
+        ))
     }
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
+        let worker_error = match &self {
+            Self::WorkerHandler { kind, .. } => Some(*kind),
+            _ => None,
+        };
         (
-            self.status,
-            Json(ErrorResponse {
-                error: self.message,
+            self.status(),
+            Json(ErrorBody {
+                code: self.code().to_string(),
+                error: self.message(),
+                worker_error,
             }),
         )
             .into_response()
     }
 }
 
-impl<E> From<E> for ApiError
-where
-    E: Into<anyhow::Error>,
-{
-    fn from(value: E) -> Self {
-        Self {
-            status: StatusCode::INTERNAL_SERVER_ERROR,
-            message: format!("{:#}", value.into()),
+impl From<JsonRejection> for ApiError {
+    fn from(value: JsonRejection) -> Self {
+        Self::InvalidRequest(value.body_text())
+    }
+}
+
+impl From<balius_runtime::Error> for ApiError {
+    fn from(value: balius_runtime::Error) -> Self {
+        use balius_runtime::Error;
+        match value {
+            Error::WorkerNotFound(id) => Self::WorkerNotFound(id),
+            Error::Handle(code, message) => Self::WorkerHandler {
+                kind: WorkerErrorKind::from_code(code),
+                message,
+            },
+            Error::Ledger(message) => Self::LedgerUnavailable(message),
+            other => Self::Internal(other.to_string()),
         }
     }
 }
 
+/// A JSON request body, which reports malformed input as an [`ApiError`].
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(ApiError))]
+pub struct ApiJson<T>(pub T);
+
 pub type ApiResult<T, E = ApiError> = std::result::Result<T, E>;
@@ -0,0 +1,85 @@
+use std::convert::Infallible;
+
+use async_trait::async_trait;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use balius_runtime::wit::balius::app::logging as wit;
+use balius_server_client::types::WorkerEvent;
+use tokio::sync::broadcast;
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+use tracing::{debug, error, info, trace, warn};
+
+/// The logging context which `sundae_strategies::events::emit` writes structured events with.
+const EVENT_CONTEXT: &str = "sundae_strategies::event";
+
+/// How many events a slow subscriber can fall behind before it starts missing them.
+const EVENT_BUFFER: usize = 1024;
+
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<WorkerEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        Self { sender }
+    }
+
+    /// Stream every event which matches the filter to the client, as server-sent events.
+    pub fn subscribe(
+        &self,
+        filter: impl Fn(&WorkerEvent) -> bool + Send + 'static,
+    ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+        let stream = BroadcastStream::new(self.sender.subscribe()).filter_map(move |event| {
+            let event = event.ok().filter(|event| filter(event))?;
+            let sse = Event::default().event("strategy").json_data(&event).ok()?;
+            Some(Ok(sse))
+        });
+        Sse::new(stream).keep_alive(KeepAlive::default())
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Logs everything a worker logs through tracing, and publishes its structured strategy events.
+pub struct EventLogger {
+    project_id: Option<String>,
+    events: EventBus,
+}
+
+impl EventLogger {
+    pub fn new(project_id: Option<String>, events: EventBus) -> Self {
+        Self { project_id, events }
+    }
+}
+
+#[async_trait]
+impl balius_runtime::logging::LoggerProvider for EventLogger {
+    async fn log(&mut self, worker_id: &str, level: wit::Level, context: String, message: String) {
+        if context == EVENT_CONTEXT {
+            match serde_json::from_str(&message) {
+                Ok(event) => {
+                    // No subscribers is not an error
+                    let _ = self.events.sender.send(WorkerEvent {
+                        worker_id: worker_id.to_string(),
+                        project_id: self.project_id.clone(),
+                        event,
+                    });
+                }
+                Err(err) => warn!(worker_id, "worker emitted a malformed event: {err}"),
+            }
+        }
+
+        match level {
+            wit::Level::Trace => trace!(worker_id, context, "{message}"),
+            wit::Level::Debug => debug!(worker_id, context, "{message}"),
+            wit::Level::Info => info!(worker_id, context, "{message}"),
+            wit::Level::Warn => warn!(worker_id, context, "{message}"),
+            wit::Level::Error | wit::Level::Critical => error!(worker_id, context, "{message}"),
+        }
+    }
+}
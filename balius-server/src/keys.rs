@@ -1,31 +1,92 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 use async_trait::async_trait;
 use balius_runtime::wit::balius::app::sign as wit;
 use pallas_crypto::key::ed25519::SecretKey;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
+use tracing::error;
+
+use crate::config::{StaticKeyConfig, static_worker_id};
+
+type StaticKeys = HashMap<String, Vec<(String, SecretKey)>>;
 
 #[derive(Clone)]
 pub struct KeyService {
     keys_dir: PathBuf,
+    static_keys: Arc<RwLock<StaticKeys>>,
 }
 
 impl KeyService {
     pub async fn new(keys_dir: PathBuf) -> Result<Self> {
         fs::create_dir_all(&keys_dir).await?;
-        Ok(Self { keys_dir })
+        Ok(Self {
+            keys_dir,
+            static_keys: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+
+    /// Replace the keys declared in config. Workers with keys declared here
+    /// never fall back to the keys stored on disk.
+    pub fn set_static_keys(&self, keys: &[StaticKeyConfig]) -> Result<()> {
+        let mut parsed = StaticKeys::new();
+        for key in keys {
+            if key.algorithm != "ed25519" {
+                bail!(
+                    "unsupported algorithm {} for key {}",
+                    key.algorithm,
+                    key.name
+                );
+            }
+            let bytes: [u8; SecretKey::SIZE] = hex::decode(&key.private_key)
+                .with_context(|| format!("private key {} is not hex encoded", key.name))?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("private key {} has the wrong length", key.name))?;
+            parsed
+                .entry(static_worker_id(&key.worker))
+                .or_default()
+                .push((key.name.clone(), SecretKey::from(bytes)));
+        }
+        *self.static_keys.write().unwrap() = parsed;
+        Ok(())
     }
 
     pub async fn get_keys(&self, project_id: String) -> Result<Vec<(String, SecretKey)>> {
-        let key_path = self.keys_dir.join(format!("{project_id}.json"));
+        if let Some(keys) = self.static_keys.read().unwrap().get(&project_id) {
+            return Ok(keys.clone());
+        }
+        let key_path = self.key_path(&project_id);
         match fs::read(&key_path).await {
             Ok(bytes) => self.parse_keys(bytes),
             _ => self.create_keys(key_path).await,
         }
     }
 
+    /// The key with the given name, generating and storing one if the worker doesn't have it yet.
+    /// Keys declared in config are never generated.
+    pub async fn get_or_create_key(&self, worker_id: &str, name: &str) -> Result<SecretKey> {
+        let mut keys = self.get_keys(worker_id.to_string()).await?;
+        if let Some((_, key)) = keys.iter().find(|(key_name, _)| key_name == name) {
+            return Ok(key.clone());
+        }
+        if self.static_keys.read().unwrap().contains_key(worker_id) {
+            bail!("worker {worker_id} has no key named {name} in config");
+        }
+        let key = SecretKey::new(rand::thread_rng());
+        keys.push((name.to_string(), key.clone()));
+        self.write_keys(&self.key_path(worker_id), &keys).await?;
+        Ok(key)
+    }
+
+    fn key_path(&self, worker_id: &str) -> PathBuf {
+        self.keys_dir.join(format!("{worker_id}.json"))
+    }
+
     fn parse_keys(&self, bytes: Vec<u8>) -> Result<Vec<(String, SecretKey)>> {
         let raw: Vec<RawKey> = serde_json::from_slice(&bytes)?;
         Ok(raw
@@ -39,7 +100,11 @@ impl KeyService {
 
     async fn create_keys(&self, key_path: PathBuf) -> Result<Vec<(String, SecretKey)>> {
         let keys = vec![("default".to_string(), SecretKey::new(rand::thread_rng()))];
+        self.write_keys(&key_path, &keys).await?;
+        Ok(keys)
+    }
 
+    async fn write_keys(&self, key_path: &Path, keys: &[(String, SecretKey)]) -> Result<()> {
         let raw: Vec<RawKey> = keys
             .iter()
             .map(|(k, v)| RawKey {
@@ -48,7 +113,7 @@ impl KeyService {
             })
             .collect();
         fs::write(key_path, serde_json::to_vec(&raw)?).await?;
-        Ok(keys)
+        Ok(())
     }
 }
 
@@ -72,12 +137,14 @@ impl PersistentSignerProvider {
 impl balius_runtime::sign::SignerProvider for PersistentSignerProvider {
     async fn add_key(&mut self, worker_id: &str, key_name: String, algorithm: String) -> Vec<u8> {
         assert!(algorithm == "ed25519");
-        let keys = self.keys.get_keys(worker_id.to_string()).await.unwrap();
-        let key = keys
-            .iter()
-            .find_map(|(name, key)| (*name == key_name).then_some(key))
-            .unwrap();
-        key.public_key().as_ref().to_vec()
+        match self.keys.get_or_create_key(worker_id, &key_name).await {
+            Ok(key) => key.public_key().as_ref().to_vec(),
+            Err(err) => {
+                // The worker will see an empty public key, and signing with it will fail
+                error!(worker_id, key_name, "failed to load signing key: {err:#}");
+                vec![]
+            }
+        }
     }
 
     async fn sign_payload(
@@ -86,11 +153,15 @@ impl balius_runtime::sign::SignerProvider for PersistentSignerProvider {
         key_name: String,
         payload: wit::Payload,
     ) -> Result<wit::Signature, wit::SignError> {
-        let keys = self.keys.get_keys(worker_id.to_string()).await.unwrap();
+        let keys = self
+            .keys
+            .get_keys(worker_id.to_string())
+            .await
+            .map_err(|err| wit::SignError::Internal(format!("{err:#}")))?;
         let key = keys
             .iter()
             .find_map(|(name, key)| (*name == key_name).then_some(key))
-            .unwrap();
+            .ok_or(wit::SignError::KeyNotFound(key_name))?;
         Ok(key.sign(payload).as_ref().to_vec())
     }
 }
@@ -4,36 +4,53 @@ use anyhow::Result;
 use axum::{
     Json, Router,
     extract::{Path, State},
-    http::StatusCode,
-    response::Html,
+    http::HeaderMap,
+    response::{Html, IntoResponse, Response},
     routing::{get, post},
 };
+use balius_server_client::types::{
+    AcknowledgeResponse, CborResponse, CreateProjectRequest, CreateProjectResponse,
+    CreateResourceRequest, CreateResourceResponse, ErrorBody, InvokeWorkerRequest, JsonResponse,
+    PartialTxResponse, ResourceKind, WorkerEvent, WorkerResponseKind, WorkerSpec,
+};
 use clap::Parser;
 use config::{AppConfig, Args};
 use dashmap::DashMap;
-use error::{ApiError, ApiResult};
+use error::{ApiError, ApiJson, ApiResult};
 use include_dir::{Dir, include_dir};
-use serde::{Deserialize, Serialize};
 use tokio::{net::TcpListener, sync::Mutex};
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{info, warn};
+use utoipa::OpenApi;
 use worker::{Worker, WorkerService};
 
-use crate::keys::KeyService;
+use crate::{events::EventBus, keys::KeyService};
 
 mod config;
 mod error;
+mod events;
 mod keys;
+mod response;
 mod worker;
 
 async fn hello_world() -> Html<&'static str> {
     Html("<h1>Hello, World!</h1>")
 }
 
+/// Create a new project.
+#[utoipa::path(
+    post,
+    path = "/projects",
+    request_body = CreateProjectRequest,
+    responses(
+        (status = 200, body = CreateProjectResponse),
+        (status = 400, body = ErrorBody),
+    ),
+)]
 async fn create_project(
     State(AppState { projects, .. }): State<AppState>,
-    Json(req): Json<CreateProjectRequest>,
+    ApiJson(req): ApiJson<CreateProjectRequest>,
 ) -> ApiResult<Json<CreateProjectResponse>> {
     let new_id = (projects.len() + 1).to_string();
     projects.insert(new_id.clone(), ProjectState {});
@@ -44,27 +61,37 @@ async fn create_project(
     }))
 }
 
-#[derive(Deserialize)]
-struct CreateProjectRequest {
-    name: String,
-}
-
-#[derive(Serialize)]
-struct CreateProjectResponse {
-    id: String,
-    name: String,
-    namespace: String,
-}
-
+/// Create a new resource. This starts a worker, which begins following the chain immediately.
+#[utoipa::path(
+    post,
+    path = "/resources",
+    request_body = CreateResourceRequest,
+    responses(
+        (status = 200, body = CreateResourceResponse),
+        (status = 400, description = "The request or its spec is malformed", body = ErrorBody),
+        (status = 404, description = "The project or a bundled worker module does not exist", body = ErrorBody),
+        (status = 502, description = "The worker module could not be fetched", body = ErrorBody),
+        (status = 503, description = "The ledger is unavailable", body = ErrorBody),
+    ),
+)]
 async fn create_resource(
     State(AppState {
+        projects,
         workers,
         worker_service,
         ..
     }): State<AppState>,
-    Json(req): Json<CreateResourceRequest>,
+    ApiJson(req): ApiJson<CreateResourceRequest>,
 ) -> ApiResult<Json<CreateResourceResponse>> {
-    let worker = worker_service.lock().await.create_worker(&req.spec).await?;
+    if !projects.contains_key(&req.project_id) {
+        return Err(ApiError::ProjectNotFound(req.project_id));
+    }
+    let spec = req.spec.parse().map_err(ApiError::invalid_spec)?;
+    let worker = worker_service
+        .lock()
+        .await
+        .create_worker(req.project_id, spec)
+        .await?;
     let id = worker.id.clone();
     workers.insert(id.clone(), worker);
 
@@ -75,53 +102,120 @@ async fn create_resource(
     }))
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct CreateResourceRequest {
-    #[allow(unused)]
-    project_id: String,
-    kind: ResourceKind,
-    spec: String,
-}
-
-#[derive(Serialize, Deserialize)]
-enum ResourceKind {
-    BaliusWorker,
-}
-
-#[derive(Serialize)]
-struct CreateResourceResponse {
-    id: String,
-    name: String,
-    kind: ResourceKind,
-}
-
+/// Call one of a worker's request handlers.
+#[utoipa::path(
+    post,
+    path = "/worker/{workerId}",
+    params(("workerId" = String, Path, description = "The id returned when the worker was created")),
+    request_body = InvokeWorkerRequest,
+    responses(
+        (
+            status = 200,
+            description = "The worker's response, as a JSON body with a `kind` field: `AcknowledgeResponse`, `JsonResponse`, `CborResponse` or `PartialTxResponse`. If `application/cbor` is accepted, CBOR and partial transactions are sent as raw bytes instead. The kind is also given in the `x-balius-response-kind` header",
+            content(
+                (serde_json::Value = "application/json"),
+                (Vec<u8> = "application/cbor"),
+            ),
+        ),
+        (status = 400, body = ErrorBody),
+        (status = 404, description = "The worker or method does not exist", body = ErrorBody),
+        (status = 500, body = ErrorBody),
+    ),
+)]
 async fn invoke_worker(
     State(AppState { workers, .. }): State<AppState>,
     Path(worker_id): Path<String>,
-    Json(req): Json<InvokeWorkerRequest>,
-) -> ApiResult<Json<serde_json::Value>> {
+    headers: HeaderMap,
+    ApiJson(req): ApiJson<InvokeWorkerRequest>,
+) -> ApiResult<Response> {
     let Some(mut worker) = workers.get_mut(&worker_id) else {
-        return Err(ApiError::new(StatusCode::NOT_FOUND, "worker not found"));
+        return Err(ApiError::WorkerNotFound(worker_id));
     };
     let response = worker.invoke(&req.method, &req.params).await?;
-    Ok(Json(response))
+    response::negotiate(response, &headers)
 }
 
-#[derive(Serialize, Deserialize)]
-struct InvokeWorkerRequest {
-    method: String,
-    params: serde_json::Value,
+/// Subscribe to the strategy events of a single worker, as server-sent events.
+#[utoipa::path(
+    get,
+    path = "/worker/{workerId}/events",
+    params(("workerId" = String, Path, description = "The id returned when the worker was created")),
+    responses(
+        (status = 200, description = "A stream of `strategy` events", body = WorkerEvent, content_type = "text/event-stream"),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+async fn worker_events(
+    State(AppState {
+        workers, events, ..
+    }): State<AppState>,
+    Path(worker_id): Path<String>,
+) -> ApiResult<Response> {
+    if !workers.contains_key(&worker_id) {
+        return Err(ApiError::WorkerNotFound(worker_id));
+    }
+    Ok(events
+        .subscribe(move |event| event.worker_id == worker_id)
+        .into_response())
+}
+
+/// Subscribe to the strategy events of every worker in a project, as server-sent events.
+#[utoipa::path(
+    get,
+    path = "/projects/{projectId}/events",
+    params(("projectId" = String, Path)),
+    responses(
+        (status = 200, description = "A stream of `strategy` events", body = WorkerEvent, content_type = "text/event-stream"),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+async fn project_events(
+    State(AppState {
+        projects, events, ..
+    }): State<AppState>,
+    Path(project_id): Path<String>,
+) -> ApiResult<Response> {
+    if !projects.contains_key(&project_id) {
+        return Err(ApiError::ProjectNotFound(project_id));
+    }
+    Ok(events
+        .subscribe(move |event| event.project_id.as_ref() == Some(&project_id))
+        .into_response())
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "balius-server"),
+    paths(
+        create_project,
+        create_resource,
+        invoke_worker,
+        worker_events,
+        project_events
+    ),
+    components(schemas(
+        ResourceKind,
+        WorkerSpec,
+        ErrorBody,
+        WorkerResponseKind,
+        AcknowledgeResponse,
+        JsonResponse,
+        CborResponse,
+        PartialTxResponse,
+        WorkerEvent,
+    ))
+)]
+struct ApiDoc;
+
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
 }
 
 static PRECOMPILED_WORKERS: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/workers");
 
 async fn serve_worker(Path(filename): Path<String>) -> ApiResult<Vec<u8>> {
     let Some(worker) = PRECOMPILED_WORKERS.get_file(&filename) else {
-        return Err(ApiError::new(
-            StatusCode::NOT_FOUND,
-            format!("worker {filename} not found"),
-        ));
+        return Err(ApiError::ModuleNotFound(filename));
     };
     Ok(worker.contents().to_vec())
 }
@@ -131,14 +225,41 @@ struct AppState {
     projects: Arc<DashMap<String, ProjectState>>,
     workers: Arc<DashMap<String, Worker>>,
     worker_service: Arc<Mutex<WorkerService>>,
+    events: EventBus,
 }
 
 struct ProjectState {}
 
+/// Reload config on SIGHUP, so static workers can be added, changed or removed without a restart.
+async fn reload_on_hangup(args: Args, state: AppState) -> Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+    let mut hangup = signal(SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        info!("reloading config");
+        let config = match AppConfig::load(args.clone()) {
+            Ok(config) => config,
+            Err(err) => {
+                warn!("could not reload config: {err:#}");
+                continue;
+            }
+        };
+        if let Err(err) = state
+            .worker_service
+            .lock()
+            .await
+            .reconcile_static_workers(config, &state.workers)
+            .await
+        {
+            warn!("could not reconcile static workers: {err:#}");
+        }
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::try_parse()?;
-    let config = AppConfig::load(args)?;
+    let config = AppConfig::load(args.clone())?;
     tracing_subscriber::fmt::init();
     info!("Hello, world!");
 
@@ -149,6 +270,7 @@ async fn main() -> Result<()> {
     }
 
     let key_service = KeyService::new(config.data_dir.join("keys")).await?;
+    let events = EventBus::new();
 
     let state = AppState {
         projects: Arc::new(DashMap::new()),
@@ -157,14 +279,27 @@ async fn main() -> Result<()> {
             config.clone(),
             key_service,
             predefined_workers,
+            events.clone(),
         )?)),
+        events,
     };
 
+    state
+        .worker_service
+        .lock()
+        .await
+        .reconcile_static_workers(config.clone(), &state.workers)
+        .await?;
+    tokio::spawn(reload_on_hangup(args, state.clone()));
+
     let app = Router::new()
         .route("/", get(hello_world))
+        .route("/openapi.json", get(openapi))
         .route("/projects", post(create_project))
         .route("/resources", post(create_resource))
+        .route("/projects/{projectId}/events", get(project_events))
         .route("/worker/{workerId}", post(invoke_worker))
+        .route("/worker/{workerId}/events", get(worker_events))
         .route("/workers/{filename}", get(serve_worker))
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http())
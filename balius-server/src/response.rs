@@ -0,0 +1,145 @@
+use axum::{
+    Json,
+    http::{HeaderMap, HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use balius_server_client::types::{
+    AcknowledgeResponse, AssetAmount, CborResponse, JsonResponse, PartialTxResponse,
+    TxOutputSummary, TxSummary, WorkerResponseKind,
+};
+use pallas_traverse::MultiEraTx;
+
+use crate::error::{ApiError, ApiResult};
+
+pub const RESPONSE_KIND_HEADER: &str = "x-balius-response-kind";
+const APPLICATION_CBOR: &str = "application/cbor";
+
+/// Turn a worker's response into an HTTP response. CBOR and partial transactions are sent as raw
+/// bytes if the client accepts `application/cbor`; everything else is wrapped in a JSON body
+/// with a `kind` field.
+pub fn negotiate(res: balius_runtime::Response, headers: &HeaderMap) -> ApiResult<Response> {
+    use balius_runtime::Response as WorkerResponse;
+    let accepts_cbor = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| {
+            value
+                .split(',')
+                .any(|t| t.trim().starts_with(APPLICATION_CBOR))
+        });
+
+    let (kind, mut response) = match res {
+        WorkerResponse::Acknowledge => {
+            let kind = WorkerResponseKind::Acknowledge;
+            (kind, Json(AcknowledgeResponse { kind }).into_response())
+        }
+        WorkerResponse::Json(x) => {
+            let kind = WorkerResponseKind::Json;
+            let value = serde_json::from_slice(&x).map_err(ApiError::internal)?;
+            (kind, Json(JsonResponse { kind, value }).into_response())
+        }
+        WorkerResponse::Cbor(x) if accepts_cbor => (WorkerResponseKind::Cbor, cbor_response(x)),
+        WorkerResponse::Cbor(x) => {
+            let kind = WorkerResponseKind::Cbor;
+            let body = CborResponse {
+                kind,
+                cbor: hex::encode(x),
+            };
+            (kind, Json(body).into_response())
+        }
+        WorkerResponse::PartialTx(x) if accepts_cbor => {
+            (WorkerResponseKind::PartialTx, cbor_response(x))
+        }
+        WorkerResponse::PartialTx(x) => {
+            let kind = WorkerResponseKind::PartialTx;
+            let body = PartialTxResponse {
+                kind,
+                summary: summarize_tx(&x),
+                tx: hex::encode(x),
+            };
+            (kind, Json(body).into_response())
+        }
+    };
+
+    response.headers_mut().insert(
+        RESPONSE_KIND_HEADER,
+        HeaderValue::from_static(kind.as_str()),
+    );
+    Ok(response)
+}
+
+fn cbor_response(bytes: Vec<u8>) -> Response {
+    ([(header::CONTENT_TYPE, APPLICATION_CBOR)], bytes).into_response()
+}
+
+/// Decode the interesting parts of a transaction, for clients which don't want to parse CBOR.
+fn summarize_tx(bytes: &[u8]) -> Option<TxSummary> {
+    let tx = MultiEraTx::decode(bytes).ok()?;
+
+    let inputs = tx
+        .inputs()
+        .iter()
+        .map(|input| format!("{}#{}", input.hash(), input.index()))
+        .collect();
+
+    let outputs = tx
+        .outputs()
+        .iter()
+        .map(|output| {
+            let address = match output.address() {
+                Ok(address) => address.to_bech32().unwrap_or_else(|_| address.to_hex()),
+                Err(_) => String::new(),
+            };
+            let value = output.value();
+            let assets = value
+                .assets()
+                .iter()
+                .flat_map(|policy| {
+                    policy.assets().into_iter().map(|asset| AssetAmount {
+                        policy_id: policy.policy().to_string(),
+                        asset_name: hex::encode(asset.name()),
+                        amount: asset.output_coin().unwrap_or_default(),
+                    })
+                })
+                .collect();
+            TxOutputSummary {
+                address,
+                lovelace: value.coin(),
+                assets,
+            }
+        })
+        .collect();
+
+    let required_signers = tx
+        .required_signers()
+        .as_alonzo()
+        .into_iter()
+        .flat_map(|signers| signers.iter())
+        .map(|signer| signer.to_string())
+        .collect();
+
+    Some(TxSummary {
+        hash: tx.hash().to_string(),
+        inputs,
+        outputs,
+        fee: tx.fee(),
+        required_signers,
+    })
+}
+
+#[tokio::test]
+pub async fn test_json_responses_have_a_kind() {
+    let worker_response = balius_runtime::Response::Json(br#"{"signer":"ab"}"#.to_vec());
+    let response = negotiate(worker_response, &HeaderMap::new()).unwrap();
+    assert_eq!(response.headers()[RESPONSE_KIND_HEADER], "json");
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({ "kind": "json", "value": { "signer": "ab" } })
+    );
+}
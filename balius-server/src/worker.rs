@@ -2,16 +2,21 @@ use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use balius_runtime::{Store, kv::memory::MemoryKv};
-use reqwest::StatusCode;
-use serde::Deserialize;
+use balius_server_client::types::WorkerSpec;
+use dashmap::DashMap;
 use serde_json::json;
-use tokio::sync::{Mutex, RwLock};
+use tokio::{
+    sync::{Mutex, RwLock},
+    task::JoinHandle,
+};
 use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
 use url::Url;
 
 use crate::{
-    config::AppConfig,
+    config::{AppConfig, StaticKeyConfig, StaticWorkerConfig, static_worker_id},
     error::{ApiError, ApiResult},
+    events::{EventBus, EventLogger},
     keys::{KeyService, PersistentSignerProvider},
 };
 
@@ -21,6 +26,59 @@ pub struct WorkerService {
     keys: KeyService,
     client: reqwest::Client,
     predefined_workers: HashMap<String, Vec<u8>>,
+    static_workers: HashMap<String, StaticWorker>,
+    events: EventBus,
+}
+
+/// A static worker as declared in config, with the keys declared for it. The worker registers
+/// its public keys when it starts, so it's restarted if either changes.
+#[derive(Clone, Debug, PartialEq)]
+struct StaticWorker {
+    def: StaticWorkerConfig,
+    keys: Vec<StaticKeyConfig>,
+}
+
+/// Every static worker declared in config, by name.
+fn declared_static_workers(config: &AppConfig) -> HashMap<String, StaticWorker> {
+    config
+        .workers
+        .iter()
+        .map(|def| {
+            let keys = config
+                .signing
+                .keys
+                .iter()
+                .filter(|key| key.worker == def.name)
+                .cloned()
+                .collect();
+            let worker = StaticWorker {
+                def: def.clone(),
+                keys,
+            };
+            (def.name.clone(), worker)
+        })
+        .collect()
+}
+
+/// The running static workers which have to stop, because they were removed from config or their
+/// definition or keys changed.
+fn outdated_static_workers(
+    running: &HashMap<String, StaticWorker>,
+    declared: &HashMap<String, StaticWorker>,
+) -> Vec<String> {
+    let mut outdated: Vec<String> = running
+        .iter()
+        .filter(|(name, worker)| declared.get(*name) != Some(*worker))
+        .map(|(name, _)| name.clone())
+        .collect();
+    outdated.sort();
+    outdated
+}
+
+/// Where to load a worker's wasm module from.
+enum WorkerModule<'a> {
+    Url(&'a Url),
+    Wasm(&'a [u8]),
 }
 
 impl WorkerService {
@@ -28,6 +86,7 @@ impl WorkerService {
         config: AppConfig,
         keys: KeyService,
         predefined_workers: HashMap<String, Vec<u8>>,
+        events: EventBus,
     ) -> Result<Self> {
         Ok(Self {
             next_id: 1,
@@ -37,73 +96,156 @@ impl WorkerService {
                 .timeout(Duration::from_secs(10))
                 .build()?,
             predefined_workers,
+            static_workers: HashMap::new(),
+            events,
         })
     }
 
-    pub async fn create_worker(&mut self, spec: &str) -> ApiResult<Worker> {
-        let parsed: WorkerSpec = serde_json::from_str(spec)?;
-
-        let id = self.next_id;
+    pub async fn create_worker(
+        &mut self,
+        project_id: String,
+        spec: WorkerSpec,
+    ) -> ApiResult<Worker> {
+        let id = self.next_id.to_string();
+        let module = if spec.url.scheme() == "file" {
+            let Some(wasm) = self.predefined_workers.get(spec.url.path()) else {
+                return Err(ApiError::ModuleNotFound(spec.url.to_string()));
+            };
+            WorkerModule::Wasm(wasm)
+        } else {
+            WorkerModule::Url(&spec.url)
+        };
         let worker = self
-            .build_balius_worker(id, &parsed.url, parsed.config)
+            .build_balius_worker(&id, Some(project_id), module, spec.config)
             .await?;
         self.next_id += 1;
         Ok(worker)
     }
 
-    async fn build_balius_worker(
+    /// Bring the running static workers in line with the given config: start
+    /// workers which are new, restart workers whose definition or keys changed,
+    /// and stop workers which are no longer declared.
+    pub async fn reconcile_static_workers(
         &mut self,
-        id: usize,
-        url: &Url,
+        config: AppConfig,
+        workers: &DashMap<String, Worker>,
+    ) -> Result<()> {
+        self.keys.set_static_keys(&config.signing.keys)?;
+
+        let declared = declared_static_workers(&config);
+        self.config = config;
+
+        let mut running = std::mem::take(&mut self.static_workers);
+        for name in outdated_static_workers(&running, &declared) {
+            info!(worker = name, "stopping static worker");
+            running.remove(&name);
+            // Wait for the worker to stop, so its store is closed before it's reopened
+            if let Some((_, worker)) = workers.remove(&static_worker_id(&name)) {
+                worker.shutdown().await;
+            }
+        }
+
+        for (name, declared) in declared {
+            let id = static_worker_id(&name);
+            if running.contains_key(&name) && workers.contains_key(&id) {
+                self.static_workers.insert(name, declared);
+                continue;
+            }
+            info!(worker = name, module = ?declared.def.module.relative(), "starting static worker");
+            match self.create_static_worker(&declared.def).await {
+                Ok(worker) => {
+                    workers.insert(id, worker);
+                    self.static_workers.insert(name, declared);
+                }
+                Err(err) => warn!(worker = name, "could not start static worker: {err}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn create_static_worker(&mut self, def: &StaticWorkerConfig) -> ApiResult<Worker> {
+        let wasm = tokio::fs::read(def.module.relative())
+            .await
+            .map_err(|err| ApiError::ModuleFetchFailed(err.to_string()))?;
+        let mut config = match &def.config {
+            Some(path) => {
+                let bytes = tokio::fs::read(path.relative())
+                    .await
+                    .map_err(ApiError::internal)?;
+                serde_json::from_slice(&bytes).map_err(ApiError::invalid_spec)?
+            }
+            None => json!({}),
+        };
+        if let Some(network) = &def.network {
+            let Some(config) = config.as_object_mut() else {
+                return Err(ApiError::invalid_spec(format!(
+                    "config for worker {} is not an object, so its network can't be set",
+                    def.name
+                )));
+            };
+            config.insert("network".into(), network.clone());
+        }
+        let id = static_worker_id(&def.name);
+        self.build_balius_worker(&id, None, WorkerModule::Wasm(&wasm), config)
+            .await
+    }
+
+    async fn build_balius_worker(
+        &self,
+        id: &str,
+        project_id: Option<String>,
+        module: WorkerModule<'_>,
         config: serde_json::Value,
     ) -> ApiResult<Worker> {
         let store_dir_path = self.config.data_dir.join("stores");
-        tokio::fs::create_dir_all(&store_dir_path).await?;
+        tokio::fs::create_dir_all(&store_dir_path)
+            .await
+            .map_err(ApiError::internal)?;
         let store_path = store_dir_path.join(format!("{id}.redb"));
         let store = Store::open(store_path, None)?;
 
         let ledger =
             balius_runtime::ledgers::u5c::Ledger::new(&balius_runtime::ledgers::u5c::Config {
-                endpoint_url: self.config.utxorpc.endpoint_url.clone(),
-                headers: self.config.utxorpc.headers.clone(),
+                endpoint_url: self.config.ledger.endpoint_url.clone(),
+                headers: self.config.ledger.headers.clone(),
             })
-            .await?;
+            .await
+            .map_err(|err| ApiError::LedgerUnavailable(err.to_string()))?;
 
         let kv = Arc::new(RwLock::new(MemoryKv::default()));
         let signer = PersistentSignerProvider::new(self.keys.clone());
+        let logger = EventLogger::new(project_id, self.events.clone());
 
         let worker = balius_runtime::RuntimeBuilder::new(store)
             .with_ledger(balius_runtime::ledgers::Ledger::U5C(ledger))
             .with_kv(balius_runtime::kv::Kv::Memory(kv))
-            .with_logger(balius_runtime::logging::Logger::Tracing)
+            .with_logger(balius_runtime::logging::Logger::Custom(Arc::new(
+                Mutex::new(logger),
+            )))
             .with_signer(balius_runtime::sign::Signer::Custom(Arc::new(Mutex::new(
                 signer,
             ))))
             .with_http(balius_runtime::http::Http::Reqwest(self.client.clone()))
             .build()?;
 
-        if url.scheme() == "file" {
-            let Some(wasm) = self.predefined_workers.get(url.path()) else {
-                return Err(ApiError::new(
-                    StatusCode::NOT_FOUND,
-                    format!("Worker {url} not found"),
-                ));
-            };
-            worker
-                .register_worker(&id.to_string(), wasm, config)
-                .await?;
-        } else {
-            worker
-                .register_worker_from_url(&id.to_string(), url, config)
-                .await?;
+        match module {
+            WorkerModule::Wasm(wasm) => worker
+                .register_worker(id, wasm, config)
+                .await
+                .map_err(|err| ApiError::ModuleFetchFailed(err.to_string()))?,
+            WorkerModule::Url(url) => worker
+                .register_worker_from_url(id, url, config)
+                .await
+                .map_err(|err| ApiError::ModuleFetchFailed(err.to_string()))?,
         }
 
         let token = CancellationToken::new();
 
         let runtime = worker.clone();
         let cancel = token.child_token();
-        let u5c_config = self.config.utxorpc.clone();
-        tokio::task::spawn(async move {
+        let u5c_config = self.config.chainsync.clone();
+        let task = tokio::task::spawn(async move {
             use balius_runtime::drivers::chainsync::{Config, run};
             let config = Config {
                 endpoint_url: u5c_config.endpoint_url,
@@ -119,6 +261,7 @@ impl WorkerService {
             id: id.to_string(),
             runtime: worker,
             token,
+            task: Some(task),
         })
     }
 }
@@ -127,26 +270,33 @@ pub struct Worker {
     pub id: String,
     runtime: balius_runtime::Runtime,
     token: CancellationToken,
+    task: Option<JoinHandle<()>>,
 }
 
 impl Worker {
+    /// Stop the worker, and wait for its chainsync task to release the runtime.
+    pub async fn shutdown(mut self) {
+        self.token.cancel();
+        if let Some(task) = self.task.take()
+            && let Err(err) = task.await
+        {
+            warn!(worker = self.id, "worker task failed while stopping: {err}");
+        }
+    }
+
     pub async fn invoke(
         &mut self,
         method: &str,
         params: &serde_json::Value,
-    ) -> ApiResult<serde_json::Value> {
-        use balius_runtime::Response;
-        let params = serde_json::to_vec(params)?;
-        let res = self
-            .runtime
+    ) -> ApiResult<balius_runtime::Response> {
+        let params = serde_json::to_vec(params).map_err(ApiError::internal)?;
+        self.runtime
             .handle_request(&self.id, method, params)
-            .await?;
-        Ok(match res {
-            Response::Acknowledge => json!({}),
-            Response::Json(x) => serde_json::from_slice(&x)?,
-            Response::Cbor(x) => json!({ "cbor": hex::encode(x) }),
-            Response::PartialTx(x) => json!({ "tx": hex::encode(x) }),
-        })
+            .await
+            .map_err(|err| match err {
+                balius_runtime::Error::NoTarget => ApiError::MethodNotFound(method.to_string()),
+                other => other.into(),
+            })
     }
 }
 
@@ -156,16 +306,62 @@ impl Drop for Worker {
     }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-#[allow(unused)]
-pub struct WorkerSpec {
-    network: String,
-    operator_version: String,
-    throughput_tier: String,
-
-    display_name: String,
-    url: Url,
-    config: serde_json::Value,
-    version: String,
+#[cfg(test)]
+fn test_config(workers: &[&str], keys: &[(&str, &str)]) -> AppConfig {
+    let endpoint = crate::config::UtxorpcConfig {
+        endpoint_url: "http://localhost:50051".into(),
+        headers: None,
+    };
+    AppConfig {
+        port: 3000,
+        data_dir: "data".into(),
+        ledger: endpoint.clone(),
+        chainsync: endpoint,
+        workers: workers
+            .iter()
+            .map(|name| StaticWorkerConfig {
+                name: name.to_string(),
+                module: format!("{name}.wasm").into(),
+                config: None,
+                network: None,
+            })
+            .collect(),
+        signing: crate::config::SigningConfig {
+            keys: keys
+                .iter()
+                .map(|(worker, private_key)| StaticKeyConfig {
+                    worker: worker.to_string(),
+                    name: "default".into(),
+                    algorithm: "ed25519".into(),
+                    private_key: private_key.to_string(),
+                })
+                .collect(),
+        },
+    }
+}
+
+#[test]
+pub fn test_static_worker_key_changes() {
+    let running = declared_static_workers(&test_config(
+        &["dca", "tsl"],
+        &[("dca", "aa"), ("tsl", "bb")],
+    ));
+    let unchanged = declared_static_workers(&test_config(
+        &["dca", "tsl"],
+        &[("dca", "aa"), ("tsl", "bb")],
+    ));
+    assert!(outdated_static_workers(&running, &unchanged).is_empty());
+
+    // Changing a worker's key restarts that worker, and only that worker
+    let rekeyed = declared_static_workers(&test_config(
+        &["dca", "tsl"],
+        &[("dca", "aa"), ("tsl", "cc")],
+    ));
+    assert_eq!(outdated_static_workers(&running, &rekeyed), vec!["tsl"]);
+
+    // So does removing it, or removing the worker
+    let unkeyed = declared_static_workers(&test_config(&["dca", "tsl"], &[("dca", "aa")]));
+    assert_eq!(outdated_static_workers(&running, &unkeyed), vec!["tsl"]);
+    let removed = declared_static_workers(&test_config(&["tsl"], &[("tsl", "bb")]));
+    assert_eq!(outdated_static_workers(&running, &removed), vec!["dca"]);
 }
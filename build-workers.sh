@@ -1,3 +1,6 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
 ROOT="$(cd "$(dirname "$0")/" && pwd)"
 pushd $ROOT
 for worker in $(ls workers); do
@@ -6,9 +6,11 @@ OFFER_AMOUNT=1000000
 RECEIVE_TOKEN="99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e15.534245525259"
 RECEIVE_AMOUNT_MIN=0
 VALID_FOR_SECS=300
-PROJECT_ID="1"
 DISPLAY_NAME="My worker"
 
+project=$(curl -s "$URL/projects" -H 'Content-Type: application/json' -d "$(jq -n --arg name "$DISPLAY_NAME" '$ARGS.named')")
+PROJECT_ID=$(jq -r ".id" <(echo "$project"))
+
 config=$(jq -n \
     --arg network "$NETWORK" \
     --argjson interval "$INTERVAL" \
@@ -35,7 +37,7 @@ spec=$(jq -n \
 payload=$(jq -n \
     --arg projectId "$PROJECT_ID" \
     --arg kind "BaliusWorker" \
-    --arg spec "$spec" \
+    --argjson spec "$spec" \
     '$ARGS.named'
 )
 
@@ -0,0 +1,270 @@
+//! Buffers callbacks until the block which triggered them is buried deep enough that it's
+//! unlikely to be rolled back.
+
+use balius_sdk::WorkerResult;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    ManagedStrategy, PoolState,
+    journal::{self, Change},
+    kv,
+};
+
+const KV_CURSOR: &str = "block_cursor";
+const KV_PENDING_RANGE: &str = "pending_range";
+const KV_PENDING_PREFIX: &str = "pending:";
+
+/// How many times a confirmed event's callback can fail before we give up on it, so one bad event
+/// can't hold up the events behind it forever.
+const MAX_FAILURES: u32 = 10;
+
+/// The latest block we've seen, and how many blocks we've seen in total.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub(crate) struct BlockCursor {
+    pub slot: u64,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) enum PendingEvent {
+    NewStrategy(ManagedStrategy),
+    NewPoolState(PoolState),
+}
+
+/// An event waiting for confirmations, stored under its own key.
+#[derive(Serialize, Deserialize, Debug)]
+struct Pending {
+    /// The block count when the event was observed.
+    block: u64,
+    slot: u64,
+    /// How many times its callback has failed.
+    #[serde(default)]
+    failures: u32,
+    event: PendingEvent,
+}
+
+/// The ids of the queued events: every id from `first` up to, but not including, `next`. Events
+/// which have been completed leave a gap until every event before them has completed too.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+struct PendingRange {
+    first: u64,
+    next: u64,
+}
+
+fn pending_key(id: u64) -> String {
+    format!("{KV_PENDING_PREFIX}{id}")
+}
+
+/// Note that we've seen an event from the given slot. Returns true if it's from a new block.
+pub(crate) fn observe_block(slot: u64) -> WorkerResult<bool> {
+    let previous: BlockCursor = kv::get(KV_CURSOR)?.unwrap_or_default();
+    if previous.count > 0 && slot <= previous.slot {
+        return Ok(false);
+    }
+    let cursor = BlockCursor {
+        slot,
+        count: previous.count + 1,
+    };
+    kv::set(KV_CURSOR, &cursor)?;
+    journal::record(slot, Change::BlockObserved(previous))?;
+    Ok(true)
+}
+
+/// Put the cursor back where it was before a block which was rolled back.
+pub(crate) fn restore_cursor(cursor: BlockCursor) -> WorkerResult<()> {
+    kv::set(KV_CURSOR, &cursor)
+}
+
+/// Hold onto an event until it has enough confirmations.
+pub(crate) fn defer(slot: u64, event: PendingEvent) -> WorkerResult<()> {
+    let cursor: BlockCursor = kv::get(KV_CURSOR)?.unwrap_or_default();
+    let mut range: PendingRange = kv::get(KV_PENDING_RANGE)?.unwrap_or_default();
+    let pending = Pending {
+        block: cursor.count,
+        slot,
+        failures: 0,
+        event,
+    };
+    kv::set(&pending_key(range.next), &pending)?;
+    range.next += 1;
+    kv::set(KV_PENDING_RANGE, &range)
+}
+
+/// Every pending event which is at least `depth` blocks deep, oldest first, along with the id to
+/// pass to [`complete`] or [`fail`] once its callback has run.
+pub(crate) fn confirmed(depth: u64) -> WorkerResult<Vec<(u64, PendingEvent)>> {
+    let range: PendingRange = kv::get(KV_PENDING_RANGE)?.unwrap_or_default();
+    let cursor: BlockCursor = kv::get(KV_CURSOR)?.unwrap_or_default();
+    // Events are queued in block order, so the confirmed ones are at the front
+    let mut confirmed = vec![];
+    for id in range.first..range.next {
+        let Some(pending) = kv::get::<Pending>(&pending_key(id))? else {
+            continue;
+        };
+        if pending.block + depth > cursor.count {
+            break;
+        }
+        confirmed.push((id, pending.event));
+    }
+    Ok(confirmed)
+}
+
+/// Remove a pending event, once its callback has run.
+pub(crate) fn complete(id: u64) -> WorkerResult<()> {
+    kv::remove(&pending_key(id))?;
+    let mut range: PendingRange = kv::get(KV_PENDING_RANGE)?.unwrap_or_default();
+    if id != range.first {
+        return Ok(());
+    }
+    // Close the gap at the front of the queue
+    range.first += 1;
+    while range.first < range.next && kv::get::<Pending>(&pending_key(range.first))?.is_none() {
+        range.first += 1;
+    }
+    kv::set(KV_PENDING_RANGE, &range)
+}
+
+/// Note that a pending event's callback failed, so it's retried on the next block. Once it has
+/// failed [`MAX_FAILURES`] times it's dropped instead, and this returns true.
+pub(crate) fn fail(id: u64) -> WorkerResult<bool> {
+    let key = pending_key(id);
+    let Some(mut pending) = kv::get::<Pending>(&key)? else {
+        return Ok(false);
+    };
+    pending.failures += 1;
+    if pending.failures < MAX_FAILURES {
+        kv::set(&key, &pending)?;
+        return Ok(false);
+    }
+    warn!(
+        slot = pending.slot,
+        failures = pending.failures,
+        "dropping a confirmed event whose callback keeps failing"
+    );
+    complete(id)?;
+    Ok(true)
+}
+
+/// Drop every pending event observed at or after the given slot, because it was rolled back.
+pub(crate) fn discard_from(slot: u64) -> WorkerResult<()> {
+    let Some(mut range) = kv::get::<PendingRange>(KV_PENDING_RANGE)? else {
+        return Ok(());
+    };
+    // Events are queued in slot order, so the rolled back ones are at the back
+    let before = range;
+    while range.next > range.first {
+        let key = pending_key(range.next - 1);
+        if let Some(pending) = kv::get::<Pending>(&key)? {
+            if pending.slot < slot {
+                break;
+            }
+            kv::remove(&key)?;
+        }
+        range.next -= 1;
+    }
+    if range.next != before.next {
+        kv::set(KV_PENDING_RANGE, &range)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn pending_slots(events: &[(u64, PendingEvent)]) -> Vec<u64> {
+    events
+        .iter()
+        .map(|(_, event)| match event {
+            PendingEvent::NewPoolState(pool) => pool.slot,
+            PendingEvent::NewStrategy(_) => panic!("only pools are deferred in these tests"),
+        })
+        .collect()
+}
+
+#[test]
+pub fn test_defer_until_confirmed() {
+    let pool = |slot| PendingEvent::NewPoolState(crate::test_pool_state(slot, 1_000_000_000));
+    assert!(observe_block(100).unwrap());
+    defer(100, pool(100)).unwrap();
+    assert!(observe_block(110).unwrap());
+    defer(110, pool(110)).unwrap();
+    // More events from a block we've already seen don't advance the cursor
+    assert!(!observe_block(110).unwrap());
+    defer(110, pool(111)).unwrap();
+
+    assert!(confirmed(2).unwrap().is_empty());
+    assert_eq!(pending_slots(&confirmed(1).unwrap()), vec![100]);
+    assert!(observe_block(120).unwrap());
+    let ready = confirmed(2).unwrap();
+    assert_eq!(pending_slots(&ready), vec![100]);
+    assert_eq!(pending_slots(&confirmed(1).unwrap()), vec![100, 110, 111]);
+
+    // Completing an event out of order leaves the others queued
+    let ids: Vec<u64> = confirmed(1)
+        .unwrap()
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    complete(ids[1]).unwrap();
+    assert_eq!(pending_slots(&confirmed(1).unwrap()), vec![100, 111]);
+    complete(ids[0]).unwrap();
+    assert_eq!(pending_slots(&confirmed(1).unwrap()), vec![111]);
+    let range: PendingRange = kv::get(KV_PENDING_RANGE).unwrap().unwrap();
+    assert_eq!((range.first, range.next), (2, 3));
+    complete(ids[2]).unwrap();
+    assert!(confirmed(0).unwrap().is_empty());
+    assert_eq!(
+        kv::keys(),
+        vec![
+            "block_cursor",
+            "journal:100",
+            "journal:110",
+            "journal:120",
+            "journal_index",
+            "journal_latest",
+            "pending_range"
+        ]
+    );
+}
+
+#[test]
+pub fn test_discard_rolled_back_events() {
+    let pool = |slot| PendingEvent::NewPoolState(crate::test_pool_state(slot, 1_000_000_000));
+    for slot in [100, 110, 120] {
+        observe_block(slot).unwrap();
+        defer(slot, pool(slot)).unwrap();
+    }
+    complete(confirmed(0).unwrap()[0].0).unwrap();
+
+    discard_from(110).unwrap();
+    assert!(pending_slots(&confirmed(0).unwrap()).is_empty());
+    let range: PendingRange = kv::get(KV_PENDING_RANGE).unwrap().unwrap();
+    assert_eq!((range.first, range.next), (1, 1));
+
+    // New events reuse the discarded ids
+    defer(115, pool(115)).unwrap();
+    assert_eq!(confirmed(0).unwrap()[0].0, 1);
+    discard_from(200).unwrap();
+    assert_eq!(pending_slots(&confirmed(0).unwrap()), vec![115]);
+    assert!(
+        kv::keys()
+            .iter()
+            .all(|key| !key.starts_with("pending:") || key == "pending:1")
+    );
+}
+
+#[test]
+pub fn test_drop_failing_events() {
+    let pool = |slot| PendingEvent::NewPoolState(crate::test_pool_state(slot, 1_000_000_000));
+    observe_block(100).unwrap();
+    defer(100, pool(100)).unwrap();
+    defer(100, pool(101)).unwrap();
+
+    let (id, _) = confirmed(0).unwrap()[0].clone();
+    for _ in 1..MAX_FAILURES {
+        assert!(!fail(id).unwrap());
+        assert_eq!(pending_slots(&confirmed(0).unwrap()), vec![100, 101]);
+    }
+    assert!(fail(id).unwrap());
+    assert_eq!(pending_slots(&confirmed(0).unwrap()), vec![101]);
+    assert!(!kv::keys().contains(&pending_key(id)));
+}
@@ -0,0 +1,50 @@
+use balius_sdk::wit::balius::app::logging;
+use serde::{Deserialize, Serialize};
+
+use crate::{SpendReason, executions::SubmitOutcome};
+
+/// The logging context structured events are written with. Hosts such as balius-server watch for
+/// log lines with this context, and forward their JSON payload to subscribers.
+pub const EVENT_CONTEXT: &str = "sundae_strategies::event";
+
+/// Something that happened to a strategy, which a UI might want to show.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StrategyEvent {
+    /// A new strategy order owned by us was observed on chain.
+    OrderObserved {
+        slot: u64,
+        /// The order's UTXO, as `txHash#index`.
+        output: String,
+    },
+    /// A strategy order we were tracking was spent.
+    OrderSpent {
+        slot: u64,
+        output: String,
+        tx_hash: String,
+        reason: SpendReason,
+    },
+    /// The chain rolled back, undoing everything at or after `slot`.
+    RolledBack {
+        slot: u64,
+        /// Orders which were spent in the undone blocks, and are tracked again.
+        restored: Vec<String>,
+        /// Orders which were created in the undone blocks, and are no longer tracked.
+        discarded: Vec<String>,
+    },
+    /// An execution was posted to the relay.
+    ExecutionSubmitted {
+        output: String,
+        outcome: SubmitOutcome,
+    },
+    /// One of the strategy's callbacks returned an error.
+    HandlerError { message: String },
+}
+
+/// Emit a structured event to the host.
+pub fn emit(event: &StrategyEvent) {
+    match serde_json::to_string(event) {
+        Ok(message) => logging::log(logging::Level::Info, EVENT_CONTEXT, &message),
+        Err(err) => tracing::warn!("could not serialize strategy event: {err}"),
+    }
+}
@@ -0,0 +1,412 @@
+//! Posting strategy executions to a relay, and keeping a history of what we posted for each
+//! order and how the order was eventually spent.
+
+use std::{cell::Cell, collections::HashMap};
+
+use balius_sdk::{_internal::Handler, Error, Json, Params, WorkerResult, http::HttpRequest, wit};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use url::Url;
+
+use crate::{
+    Network, SpendReason, kv,
+    types::{Order, OutputReference, SubmitSSE},
+};
+
+const KV_HISTORY_INDEX: &str = "execution_index";
+const KV_HISTORY_PREFIX: &str = "executions:";
+const KV_DRY_RUN_PREFIX: &str = "dry_run:";
+
+thread_local! {
+    /// The slot of the chain event being handled, whatever the strategy's confirmation depth.
+    static CURRENT_SLOT: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Note the slot of the chain event we're about to handle, so executions submitted while
+/// handling it are recorded against it.
+pub(crate) fn observe_slot(slot: u64) {
+    CURRENT_SLOT.set(slot);
+}
+
+pub(crate) fn current_slot() -> u64 {
+    CURRENT_SLOT.get()
+}
+
+/// How many times we try to post an execution within one event. Workers can't sleep, so we only
+/// retry once, straight away; an execution which still fails is recorded as
+/// [`SubmitOutcome::Failed`], which doesn't count as submitted, so the strategy's next callback
+/// for a later block submits it again.
+const SUBMIT_ATTEMPTS: usize = 2;
+
+/// What happened when we submitted a strategy execution.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum SubmitOutcome {
+    /// The relay accepted the execution.
+    Accepted { status: u16 },
+    /// The relay refused the execution; submitting it again won't help.
+    Rejected { status: u16, message: String },
+    /// The relay couldn't be reached, or kept failing, after retrying once. The execution can be
+    /// submitted again on a later event.
+    Failed {
+        status: Option<u16>,
+        message: String,
+    },
+    /// The submitter is a dry run, and recorded the execution instead of sending it.
+    Recorded,
+    /// We'd already submitted an execution of this order for the same validity window, so
+    /// nothing was sent.
+    Duplicate,
+}
+
+impl SubmitOutcome {
+    /// Whether the submitter took the execution, so it shouldn't be submitted again.
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, Self::Accepted { .. } | Self::Recorded)
+    }
+}
+
+/// Where strategy executions get sent. Workers can let their config pick one, e.g.
+/// `"submitter": { "kind": "relays", "urls": ["http://localhost:8080/publish"] }`.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Submitter {
+    /// Post to the network's relay.
+    #[default]
+    Default,
+    /// Post to a relay of our choosing, such as one in front of our own scooper.
+    Relay { url: Url },
+    /// Post to every one of these relays for redundancy; the execution is accepted if any of them
+    /// accepts it.
+    Relays { urls: Vec<Url> },
+    /// Don't send anything; log the signed execution and record it in the KV store.
+    DryRun,
+}
+
+/// A signed execution which a dry run submitter didn't send.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DryRunExecution {
+    pub window: Window,
+    /// The hex encoded signed execution, as it would have been posted.
+    pub data: String,
+}
+
+impl Submitter {
+    pub(crate) fn submit(
+        &self,
+        network: &Network,
+        order: &OutputReference,
+        window: Window,
+        body: &SubmitSSE,
+    ) -> Result<SubmitOutcome, Error> {
+        let urls = match self {
+            Self::Default => {
+                let Some(url) = network.relay_url() else {
+                    return Err(Error::Internal(
+                        "no relay is known for this network; configure a relay submitter".into(),
+                    ));
+                };
+                vec![url]
+            }
+            Self::Relay { url } => vec![url.clone()],
+            Self::Relays { urls } => urls.clone(),
+            Self::DryRun => {
+                info!(
+                    "dry run, not submitting execution of {order}: {}",
+                    body.data
+                );
+                let key = format!("{KV_DRY_RUN_PREFIX}{order}");
+                let mut recorded: Vec<DryRunExecution> = kv::get(&key)?.unwrap_or_default();
+                recorded.push(DryRunExecution {
+                    window,
+                    data: body.data.clone(),
+                });
+                kv::set(&key, &recorded)?;
+                return Ok(SubmitOutcome::Recorded);
+            }
+        };
+
+        let mut outcome = None;
+        for url in urls {
+            info!("posting execution of {order} to {url}: {}", body.data);
+            let result = post(&url, body)?;
+            if result.is_accepted() || outcome.is_none() {
+                outcome = Some(result);
+            }
+        }
+        outcome.ok_or_else(|| Error::Internal("no relays are configured".into()))
+    }
+}
+
+/// The executions a dry run submitter recorded for an order.
+pub fn dry_runs(order: &OutputReference) -> WorkerResult<Vec<DryRunExecution>> {
+    Ok(kv::get(&format!("{KV_DRY_RUN_PREFIX}{order}"))?.unwrap_or_default())
+}
+
+/// The validity window of a submitted execution, as finite POSIX millisecond bounds.
+pub type Window = (Option<u64>, Option<u64>);
+
+/// An execution we signed and handed to a submitter.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExecutionRecord {
+    /// The slot of the chain event we were handling when the execution was submitted.
+    pub slot: u64,
+    /// When the execution was submitted, in POSIX milliseconds: the start of `slot`. Missing
+    /// from records made by older versions of this library.
+    #[serde(default)]
+    pub submitted_at: Option<u64>,
+    pub window: Window,
+    pub details: Order,
+    /// The hex encoded signature, if the order is authorized by one of our keys.
+    pub signature: Option<String>,
+    pub outcome: SubmitOutcome,
+}
+
+impl ExecutionRecord {
+    /// Record an execution submitted while handling the current chain event.
+    pub(crate) fn new(
+        network: &Network,
+        window: Window,
+        details: Order,
+        signature: Option<String>,
+        outcome: SubmitOutcome,
+    ) -> Self {
+        let slot = current_slot();
+        Self {
+            slot,
+            submitted_at: network.to_unix_time(slot).ok(),
+            window,
+            details,
+            signature,
+            outcome,
+        }
+    }
+}
+
+/// How an order we submitted executions for was spent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderSpend {
+    pub slot: u64,
+    pub tx_hash: String,
+    pub reason: SpendReason,
+}
+
+/// Everything we've submitted for an order, oldest first. Kept until the order's spend is too old
+/// to be rolled back.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExecutionHistory {
+    /// The order's UTXO, as `txHash#index`.
+    pub order: String,
+    pub executions: Vec<ExecutionRecord>,
+    /// Set once the order has been spent.
+    pub spent: Option<OrderSpend>,
+}
+
+fn history_key(order: &str) -> String {
+    format!("{KV_HISTORY_PREFIX}{order}")
+}
+
+/// The execution history of an order, if we've submitted anything for it.
+pub fn history(order: &OutputReference) -> WorkerResult<Option<ExecutionHistory>> {
+    kv::get(&history_key(&order.to_string()))
+}
+
+/// The execution history of every order we've submitted anything for.
+pub fn all_histories() -> WorkerResult<Vec<ExecutionHistory>> {
+    let index: Vec<String> = kv::get(KV_HISTORY_INDEX)?.unwrap_or_default();
+    let mut histories = vec![];
+    for order in index {
+        if let Some(history) = kv::get(&history_key(&order))? {
+            histories.push(history);
+        }
+    }
+    Ok(histories)
+}
+
+/// Whether an execution of this order has already been accepted for the given window.
+pub(crate) fn already_submitted(order: &OutputReference, window: Window) -> WorkerResult<bool> {
+    Ok(history(order)?.is_some_and(|history| {
+        history
+            .executions
+            .iter()
+            .any(|record| record.window == window && record.outcome.is_accepted())
+    }))
+}
+
+/// Add an execution to an order's history.
+pub(crate) fn record_execution(
+    order: &OutputReference,
+    record: ExecutionRecord,
+) -> WorkerResult<()> {
+    let mut history = match history(order)? {
+        Some(history) => history,
+        None => {
+            let mut index: Vec<String> = kv::get(KV_HISTORY_INDEX)?.unwrap_or_default();
+            index.push(order.to_string());
+            kv::set(KV_HISTORY_INDEX, &index)?;
+            ExecutionHistory {
+                order: order.to_string(),
+                executions: vec![],
+                spent: None,
+            }
+        }
+    };
+    history.executions.push(record);
+    kv::set(&history_key(&history.order), &history)
+}
+
+/// Note how an order was spent, if we've submitted anything for it; None undoes this after a
+/// rollback.
+pub(crate) fn record_spend(order: &OutputReference, spend: Option<OrderSpend>) -> WorkerResult<()> {
+    let Some(mut history) = history(order)? else {
+        return Ok(());
+    };
+    history.spent = spend;
+    kv::set(&history_key(&history.order), &history)
+}
+
+/// Forget everything we submitted for an order: once its spend is too old to be rolled back, or
+/// when a rollback shows the order never existed.
+pub(crate) fn forget(order: &OutputReference) -> WorkerResult<()> {
+    let key = order.to_string();
+    kv::remove(&format!("{KV_DRY_RUN_PREFIX}{key}"))?;
+    if history(order)?.is_none() {
+        return Ok(());
+    }
+    let mut index: Vec<String> = kv::get(KV_HISTORY_INDEX)?.unwrap_or_default();
+    index.retain(|o| o != &key);
+    kv::set(KV_HISTORY_INDEX, &index)?;
+    kv::remove(&history_key(&key))
+}
+
+/// Serves the `get-executions` request, returning the execution history of the order given as
+/// the `order` param, or of every order if there isn't one.
+#[derive(Clone)]
+pub(crate) struct GetExecutions;
+
+impl Handler for GetExecutions {
+    fn handle(&self, _: wit::Config, event: wit::Event) -> Result<wit::Response, wit::HandleError> {
+        let params: Params<HashMap<String, String>> = event.try_into()?;
+        let histories = match params.get("order") {
+            Some(order) => kv::get::<ExecutionHistory>(&history_key(order))?
+                .into_iter()
+                .collect(),
+            None => all_histories()?,
+        };
+        Ok(Json(histories).try_into()?)
+    }
+}
+
+/// Post an execution to a relay, retrying a transient failure once.
+fn post(url: &Url, body: &SubmitSSE) -> Result<SubmitOutcome, Error> {
+    let mut outcome = None;
+    for attempt in 1..=SUBMIT_ATTEMPTS {
+        let (status, message) = match HttpRequest::post(url.clone()).json(body)?.send() {
+            Ok(response) => {
+                let message = String::from_utf8_lossy(&response.body).into_owned();
+                if (200..300).contains(&response.status) {
+                    return Ok(SubmitOutcome::Accepted {
+                        status: response.status,
+                    });
+                }
+                if !is_transient(response.status) {
+                    return Ok(SubmitOutcome::Rejected {
+                        status: response.status,
+                        message,
+                    });
+                }
+                (Some(response.status), message)
+            }
+            Err(err) => (None, format!("{err:?}")),
+        };
+        warn!(
+            attempt,
+            ?status,
+            "relay failed to accept execution: {message}"
+        );
+        outcome = Some(SubmitOutcome::Failed { status, message });
+    }
+    Ok(outcome.expect("we make at least one attempt"))
+}
+
+fn is_transient(status: u16) -> bool {
+    status == 408 || status == 429 || status >= 500
+}
+
+#[cfg(test)]
+fn test_record(network: &Network) -> ExecutionRecord {
+    ExecutionRecord::new(
+        network,
+        (Some(1666656000000), Some(1666657200000)),
+        Order::Strategy {
+            auth: crate::types::StrategyAuthorization::Signature {
+                signer: vec![0xab; 32],
+            },
+        },
+        None,
+        SubmitOutcome::Accepted { status: 200 },
+    )
+}
+
+#[test]
+pub fn test_execution_record_time() {
+    observe_slot(86400);
+    let record = test_record(&Network::Preview);
+    assert_eq!(record.slot, 86400);
+    // Preview's slots are a second long, from 2022-10-25T00:00:00Z
+    assert_eq!(record.submitted_at, Some(1666742400000));
+
+    // Records from before the time was kept still load
+    let mut json = serde_json::to_value(&record).unwrap();
+    json.as_object_mut().unwrap().remove("submitted_at");
+    let old: ExecutionRecord = serde_json::from_value(json).unwrap();
+    assert_eq!(old.submitted_at, None);
+}
+
+#[test]
+pub fn test_prune_histories() {
+    use crate::{
+        journal::{self, Change},
+        test_strategy,
+        types::test_output,
+    };
+
+    let horizon = journal::ROLLBACK_HORIZON_SLOTS;
+    observe_slot(100);
+    for id in 1..=3 {
+        record_execution(&test_output(id), test_record(&Network::Preview)).unwrap();
+    }
+    let spend = OrderSpend {
+        slot: 200,
+        tx_hash: hex::encode([0xcd; 32]),
+        reason: SpendReason::Executed,
+    };
+    record_spend(&test_output(1), Some(spend.clone())).unwrap();
+    journal::record(200, Change::OrderRemoved(test_strategy(1))).unwrap();
+    record_spend(&test_output(2), Some(spend)).unwrap();
+    journal::record(300, Change::OrderRemoved(test_strategy(2))).unwrap();
+
+    // Histories of spent orders are kept while the spend could still be rolled back
+    journal::record(200 + horizon, Change::OrderAdded(test_output(4))).unwrap();
+    assert_eq!(all_histories().unwrap().len(), 3);
+
+    // and forgotten after
+    journal::record(200 + horizon + 1, Change::OrderAdded(test_output(5))).unwrap();
+    let remaining: Vec<String> = all_histories()
+        .unwrap()
+        .into_iter()
+        .map(|history| history.order)
+        .collect();
+    assert_eq!(
+        remaining,
+        vec![test_output(2).to_string(), test_output(3).to_string()]
+    );
+    assert!(history(&test_output(1)).unwrap().is_none());
+
+    // Orders which a rollback shows never existed are forgotten straight away
+    forget(&test_output(3)).unwrap();
+    assert_eq!(
+        kv::get::<Vec<String>>(KV_HISTORY_INDEX).unwrap(),
+        Some(vec![test_output(2).to_string()])
+    );
+}
@@ -0,0 +1,234 @@
+//! A journal of the changes we've made to our state in recent slots, so they can be undone if
+//! the chain rolls back.
+
+use balius_sdk::WorkerResult;
+use serde::{Deserialize, Serialize};
+
+use crate::{ManagedStrategy, confirmations::BlockCursor, executions, kv, types::OutputReference};
+
+/// The slots which have journal entries, oldest first.
+const KV_JOURNAL_INDEX: &str = "journal_index";
+/// The newest slot in the index, so most changes don't need to read the whole index.
+const KV_JOURNAL_LATEST: &str = "journal_latest";
+const KV_JOURNAL_PREFIX: &str = "journal:";
+
+/// How far back we keep journal entries. Cardano never rolls back more than k = 2160 blocks, and
+/// the chain is guaranteed to grow by k blocks every 3k/f = 129600 slots.
+pub(crate) const ROLLBACK_HORIZON_SLOTS: u64 = 129600;
+
+/// A single change to our state, with enough information to revert it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) enum Change {
+    OrderAdded(OutputReference),
+    OrderRemoved(ManagedStrategy),
+    /// A pool's latest state changed; holds the key of the state from before, if we had seen the
+    /// pool. See [`crate::pools::update`].
+    PoolUpdated {
+        ident: Vec<u8>,
+        previous: Option<String>,
+    },
+    /// We saw a new block; holds the cursor from before it.
+    BlockObserved(BlockCursor),
+}
+
+fn slot_key(slot: u64) -> String {
+    format!("{KV_JOURNAL_PREFIX}{slot}")
+}
+
+/// Record a change made while processing the given slot.
+pub(crate) fn record(slot: u64, change: Change) -> WorkerResult<()> {
+    let latest: Option<u64> = kv::get(KV_JOURNAL_LATEST)?;
+    if latest != Some(slot) {
+        let mut index: Vec<u64> = kv::get(KV_JOURNAL_INDEX)?.unwrap_or_default();
+        if let Err(position) = index.binary_search(&slot) {
+            index.insert(position, slot);
+        }
+        let newest = *index.last().expect("we just added a slot");
+        let expired = index.partition_point(|s| s + ROLLBACK_HORIZON_SLOTS < newest);
+        for old in index.drain(..expired) {
+            let key = slot_key(old);
+            let changes: Vec<Change> = kv::get(&key)?.unwrap_or_default();
+            for change in changes {
+                match change {
+                    Change::PoolUpdated {
+                        previous: Some(snapshot),
+                        ..
+                    } => kv::remove(&snapshot)?,
+                    // The order's spend is final, so we no longer need what we submitted for it
+                    Change::OrderRemoved(order) => executions::forget(&order.output)?,
+                    _ => {}
+                }
+            }
+            kv::remove(&key)?;
+        }
+        kv::set(KV_JOURNAL_INDEX, &index)?;
+        kv::set(KV_JOURNAL_LATEST, &newest)?;
+    }
+
+    let key = slot_key(slot);
+    let mut changes: Vec<Change> = kv::get(&key)?.unwrap_or_default();
+    changes.push(change);
+    kv::set(&key, &changes)
+}
+
+/// Forget every change made at or after the given slot, returning them newest first so they can
+/// be reverted in order.
+pub(crate) fn rollback(slot: u64) -> WorkerResult<Vec<Change>> {
+    let Some(mut index) = kv::get::<Vec<u64>>(KV_JOURNAL_INDEX)? else {
+        return Ok(vec![]);
+    };
+    let split = index.partition_point(|s| *s < slot);
+    if split == index.len() {
+        return Ok(vec![]);
+    }
+
+    let mut undone = vec![];
+    for undone_slot in index.split_off(split).into_iter().rev() {
+        let key = slot_key(undone_slot);
+        let changes: Vec<Change> = kv::get(&key)?.unwrap_or_default();
+        undone.extend(changes.into_iter().rev());
+        kv::remove(&key)?;
+    }
+    kv::set(KV_JOURNAL_INDEX, &index)?;
+    match index.last() {
+        Some(latest) => kv::set(KV_JOURNAL_LATEST, latest)?,
+        None => kv::remove(KV_JOURNAL_LATEST)?,
+    }
+
+    Ok(undone)
+}
+
+#[test]
+pub fn test_journal_rollback() {
+    let cursor = BlockCursor { slot: 90, count: 4 };
+    record(100, Change::OrderAdded(crate::types::test_output(1))).unwrap();
+    record(200, Change::OrderAdded(crate::types::test_output(2))).unwrap();
+    record(200, Change::BlockObserved(cursor)).unwrap();
+    record(300, Change::OrderAdded(crate::types::test_output(3))).unwrap();
+    assert_eq!(
+        kv::get::<Vec<u64>>(KV_JOURNAL_INDEX).unwrap(),
+        Some(vec![100, 200, 300])
+    );
+    assert_eq!(kv::get::<u64>(KV_JOURNAL_LATEST).unwrap(), Some(300));
+
+    // Everything from slot 150 on is undone, newest first
+    let undone = rollback(150).unwrap();
+    assert_eq!(undone.len(), 3);
+    assert!(
+        matches!(&undone[0], Change::OrderAdded(output) if *output == crate::types::test_output(3))
+    );
+    assert!(matches!(&undone[1], Change::BlockObserved(c) if *c == cursor));
+    assert!(
+        matches!(&undone[2], Change::OrderAdded(output) if *output == crate::types::test_output(2))
+    );
+
+    assert_eq!(
+        kv::get::<Vec<u64>>(KV_JOURNAL_INDEX).unwrap(),
+        Some(vec![100])
+    );
+    assert_eq!(kv::get::<u64>(KV_JOURNAL_LATEST).unwrap(), Some(100));
+    assert_eq!(
+        kv::keys(),
+        vec!["journal:100", "journal_index", "journal_latest"]
+    );
+
+    // Nothing left after slot 100 to undo
+    assert!(rollback(101).unwrap().is_empty());
+
+    // Undoing the last slot empties the journal
+    assert_eq!(rollback(100).unwrap().len(), 1);
+    assert_eq!(kv::get::<Vec<u64>>(KV_JOURNAL_INDEX).unwrap(), Some(vec![]));
+    assert_eq!(kv::keys(), vec!["journal_index"]);
+}
+
+#[test]
+pub fn test_journal_pruning() {
+    use crate::pools;
+
+    let ident = crate::types::test_pool().0.identifier;
+    assert!(pools::update(&crate::test_pool_state(100, 1_000_000_000)).unwrap());
+    assert!(pools::update(&crate::test_pool_state(200, 1_100_000_000)).unwrap());
+    let snapshot = format!("pool_snapshot:200:{}", hex::encode(&ident));
+    assert!(kv::keys().contains(&snapshot));
+
+    // Entries are kept until they fall beyond the rollback horizon
+    record(
+        100 + ROLLBACK_HORIZON_SLOTS,
+        Change::OrderAdded(crate::types::test_output(1)),
+    )
+    .unwrap();
+    assert_eq!(
+        kv::get::<Vec<u64>>(KV_JOURNAL_INDEX).unwrap(),
+        Some(vec![100, 200, 100 + ROLLBACK_HORIZON_SLOTS])
+    );
+
+    // Pruning removes the expired slots, and the pool snapshots they held
+    record(
+        200 + ROLLBACK_HORIZON_SLOTS + 1,
+        Change::OrderAdded(crate::types::test_output(2)),
+    )
+    .unwrap();
+    assert_eq!(
+        kv::get::<Vec<u64>>(KV_JOURNAL_INDEX).unwrap(),
+        Some(vec![
+            100 + ROLLBACK_HORIZON_SLOTS,
+            200 + ROLLBACK_HORIZON_SLOTS + 1
+        ])
+    );
+    let keys = kv::keys();
+    assert!(!keys.contains(&"journal:100".to_string()));
+    assert!(!keys.contains(&"journal:200".to_string()));
+    assert!(!keys.contains(&snapshot));
+
+    // A rollback past the horizon can't undo what was pruned
+    assert_eq!(rollback(0).unwrap().len(), 2);
+    assert_eq!(pools::latest(&ident).unwrap().unwrap().slot, 200);
+}
+
+#[test]
+pub fn test_journal_pool_restore() {
+    use crate::{pools, types::AssetId};
+
+    let ident = crate::types::test_pool().0.identifier;
+    assert!(pools::update(&crate::test_pool_state(100, 1_000_000_000)).unwrap());
+    assert!(pools::update(&crate::test_pool_state(200, 1_100_000_000)).unwrap());
+    assert!(pools::update(&crate::test_pool_state(200, 1_200_000_000)).unwrap());
+    assert!(pools::update(&crate::test_pool_state(300, 1_300_000_000)).unwrap());
+    // An older state than the one we have is ignored
+    assert!(!pools::update(&crate::test_pool_state(250, 1_250_000_000)).unwrap());
+    assert_eq!(
+        pools::latest(&ident).unwrap().unwrap().utxo.coin,
+        1_300_000_000
+    );
+
+    // Reverting in order puts back the state from before the first update in slot 200
+    let mut snapshots = vec![];
+    for change in rollback(200).unwrap() {
+        let Change::PoolUpdated { ident, previous } = change else {
+            panic!("only pools were updated");
+        };
+        pools::restore(&ident, previous.as_deref()).unwrap();
+        snapshots.extend(previous);
+    }
+    let restored = pools::latest(&ident).unwrap().unwrap();
+    assert_eq!(restored.slot, 100);
+    assert_eq!(restored.utxo.coin, 1_000_000_000);
+    let sberry: AssetId = restored.pool_datum.assets.1.clone().into();
+    let ada: AssetId = restored.pool_datum.assets.0.clone().into();
+    assert_eq!(pools::for_pair(&ada, &sberry).unwrap().len(), 1);
+    for snapshot in snapshots {
+        kv::remove(&snapshot).unwrap();
+    }
+
+    // Reverting the slot we first saw the pool in forgets it entirely
+    for change in rollback(100).unwrap() {
+        let Change::PoolUpdated { ident, previous } = change else {
+            panic!("only pools were updated");
+        };
+        assert!(previous.is_none());
+        pools::restore(&ident, None).unwrap();
+    }
+    assert!(pools::latest(&ident).unwrap().is_none());
+    assert!(pools::for_pair(&ada, &sberry).unwrap().is_empty());
+    assert_eq!(kv::keys(), vec!["journal_index", "pool_index"]);
+}
@@ -1,20 +1,52 @@
 use balius_sdk::{Config, Error, Json, Params, WorkerResult};
 use serde::Serialize;
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::OnceLock,
+};
+
+use crate::STRATEGY_KEY;
+
+/// The names of the keys this worker claims orders for, in the order they were configured.
+static SIGNING_KEYS: OnceLock<Vec<String>> = OnceLock::new();
+
+pub(crate) fn set_signing_keys(names: Vec<String>) {
+    // A worker is only built once per instance; later calls keep the first configuration
+    let _ = SIGNING_KEYS.set(names);
+}
+
+/// The key to sign with when an order doesn't say which of ours authorizes it: the first
+/// configured key.
+pub(crate) fn primary_key() -> &'static str {
+    SIGNING_KEYS
+        .get()
+        .and_then(|names| names.first())
+        .map_or(STRATEGY_KEY, String::as_str)
+}
 
 pub fn get_signer_key<T>(
     _: Config<T>,
     _: Params<HashMap<String, String>>,
 ) -> WorkerResult<Json<SignerKey>> {
-    let Some(key) = balius_sdk::get_public_keys().remove("default") else {
+    let public_keys = balius_sdk::get_public_keys();
+    let Some(key) = public_keys.get(primary_key()) else {
         return Err(Error::Internal("key not found".into()));
     };
+    let configured = SIGNING_KEYS.get().map(Vec::as_slice).unwrap_or_default();
     Ok(Json(SignerKey {
         signer: hex::encode(key),
+        signers: public_keys
+            .iter()
+            .filter(|(name, _)| configured.contains(name))
+            .map(|(name, key)| (name.clone(), hex::encode(key)))
+            .collect(),
     }))
 }
 
 #[derive(Serialize)]
 pub struct SignerKey {
+    /// The first configured key, which orders should be signed by unless told otherwise.
     signer: String,
+    /// Every configured key, by name.
+    signers: BTreeMap<String, String>,
 }
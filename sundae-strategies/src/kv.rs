@@ -1,10 +1,12 @@
-use balius_sdk::{WorkerResult, wit::balius::app::kv};
+use balius_sdk::WorkerResult;
+#[cfg(not(test))]
+use balius_sdk::wit::balius::app::kv;
 use serde::{Deserialize, Serialize};
 
-/// Retrieve a value from the KV store. Returns None if the value does not already exist.
+/// Retrieve a value from the KV store. Returns None if the value does not already exist, or was removed.
 pub fn get<D: for<'a> Deserialize<'a>>(key: &str) -> WorkerResult<Option<D>> {
     match kv::get_value(key) {
-        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Ok(bytes) => Ok(serde_json::from_slice::<Option<D>>(&bytes)?),
         Err(kv::KvError::NotFound(_)) => Ok(None),
         Err(err) => Err(err.into()),
     }
@@ -15,3 +17,56 @@ pub fn set<S: Serialize>(key: &str, value: &S) -> WorkerResult<()> {
     kv::set_value(key, &serde_json::to_vec(value)?)?;
     Ok(())
 }
+
+/// Remove a value from the KV store. The store has no way to delete keys, so this overwrites the
+/// value with `null`, which [`get`] treats as missing.
+pub fn remove(key: &str) -> WorkerResult<()> {
+    kv::set_value(key, b"null")?;
+    Ok(())
+}
+
+/// Tests run natively, where the host's KV store isn't available, so they get an in-memory store
+/// per test thread instead.
+#[cfg(test)]
+mod kv {
+    use std::{cell::RefCell, collections::HashMap};
+
+    pub use balius_sdk::wit::balius::app::kv::KvError;
+
+    thread_local! {
+        static VALUES: RefCell<HashMap<String, Vec<u8>>> = RefCell::new(HashMap::new());
+    }
+
+    pub fn get_value(key: &str) -> Result<Vec<u8>, KvError> {
+        VALUES.with_borrow(|values| {
+            values
+                .get(key)
+                .cloned()
+                .ok_or_else(|| KvError::NotFound(key.to_string()))
+        })
+    }
+
+    pub fn set_value(key: &str, value: &[u8]) -> Result<(), KvError> {
+        VALUES.with_borrow_mut(|values| values.insert(key.to_string(), value.to_vec()));
+        Ok(())
+    }
+
+    /// Every key which holds something other than `null`.
+    pub fn keys() -> Vec<String> {
+        VALUES.with_borrow(|values| {
+            let mut keys: Vec<String> = values
+                .iter()
+                .filter(|(_, value)| value.as_slice() != b"null")
+                .map(|(key, _)| key.clone())
+                .collect();
+            keys.sort();
+            keys
+        })
+    }
+}
+
+/// Every key currently holding a value.
+#[cfg(test)]
+pub(crate) fn keys() -> Vec<String> {
+    kv::keys()
+}
@@ -1,53 +1,210 @@
+mod confirmations;
+pub mod events;
+pub mod executions;
+mod journal;
 pub mod keys;
 pub mod kv;
+pub mod orders;
+pub mod pools;
+pub mod time;
+pub mod tokens;
 pub mod types;
 
 use balius_sdk::{
-    _internal::Handler,
-    Ack, Config, Error, Tx, Utxo, UtxoMatcher, Worker, WorkerResult,
-    http::{HttpRequest, HttpResponse},
-    wit,
+    _internal::Handler, Ack, Config, Error, Tx, Utxo, UtxoMatcher, Worker, WorkerResult, wit,
 };
 use serde::{Deserialize, Serialize};
-use tracing::{info, trace};
+use tracing::{info, trace, warn};
 use url::Url;
 use utxorpc_spec::utxorpc::v1alpha::cardano::TxOutput;
 
 use crate::{
+    confirmations::PendingEvent,
+    events::StrategyEvent,
+    executions::{ExecutionRecord, GetExecutions, OrderSpend, SubmitOutcome, Submitter},
+    journal::Change,
     keys::get_signer_key,
     types::{
-        Interval, Order, OrderDatum, OutputReference, PoolDatum, SignedStrategyExecution,
-        StrategyAuthorization, StrategyExecution, SubmitSSE, TransactionId, serialize,
+        Interval, Order, OrderDatum, OrderRedeemer, OutputReference, PoolDatum,
+        SignedStrategyExecution, StrategyAuthorization, StrategyExecution, SubmitSSE,
+        TransactionId, Value, serialize,
     },
 };
 
 /// Which network is this strategy running against?
-#[derive(Deserialize)]
+#[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum Network {
     Preview,
+    Preprod,
     Mainnet,
+    /// A private testnet or local devnet.
+    Custom(CustomNetwork),
 }
 
+/// The parameters of a network which isn't built in.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CustomNetwork {
+    /// When the chain started, in POSIX seconds.
+    pub system_start: u64,
+    /// The length of a slot after the Shelley hard fork, in milliseconds. Must not be zero.
+    #[serde(
+        default = "default_slot_length",
+        deserialize_with = "deserialize_slot_length"
+    )]
+    pub slot_length: u64,
+    /// The first slot of the Shelley era; earlier slots are 20 second Byron slots. Zero for
+    /// networks which started in Shelley or later.
+    #[serde(default)]
+    pub shelley_start_slot: u64,
+    /// Where to post strategy executions.
+    pub relay_url: Option<Url>,
+    /// The hash of the Sundae v3 order script, hex encoded.
+    #[serde(default, deserialize_with = "deserialize_hex")]
+    pub order_script_hash: Option<Vec<u8>>,
+    /// The hash of the Sundae v3 pool script, hex encoded.
+    #[serde(default, deserialize_with = "deserialize_hex")]
+    pub pool_script_hash: Option<Vec<u8>>,
+    /// Addresses holding Sundae v3 orders, bech32 encoded. See [`SundaeAddresses`].
+    #[serde(default, deserialize_with = "deserialize_addresses")]
+    pub order_addresses: Vec<Vec<u8>>,
+    /// Addresses holding Sundae v3 pools, bech32 encoded.
+    #[serde(default, deserialize_with = "deserialize_addresses")]
+    pub pool_addresses: Vec<Vec<u8>>,
+    /// The ADA deposit held in every order, in lovelace.
+    #[serde(default = "default_order_deposit")]
+    pub order_deposit: u64,
+}
+
+fn default_order_deposit() -> u64 {
+    ORDER_DEPOSIT_LOVELACE
+}
+
+fn default_slot_length() -> u64 {
+    1000
+}
+
+fn deserialize_slot_length<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match u64::deserialize(deserializer)? {
+        0 => Err(serde::de::Error::custom(
+            "slot_length must be greater than zero",
+        )),
+        slot_length => Ok(slot_length),
+    }
+}
+
+fn deserialize_hex<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let Some(encoded) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    hex::decode(encoded)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn deserialize_addresses<'de, D>(deserializer: D) -> Result<Vec<Vec<u8>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|address| {
+            pallas_addresses::Address::from_bech32(address)
+                .map(|address| address.to_vec())
+                .map_err(serde::de::Error::custom)
+        })
+        .collect()
+}
+
+/// The address every Sundae v3 pool on mainnet sits at.
+const MAINNET_POOL_ADDRESS: &str = "addr1x8srqftqemf0mjlukfszd97ljuxdp44r372txfcr75wrz26rnxqnmtv3hdu2t6chcfhl2zzjh36a87nmd6dwsu3jenqsslnz7e";
+
+/// The Sundae v3 pool script on mainnet, the payment credential of every v3 pool address.
+const MAINNET_POOL_SCRIPT_HASH: [u8; 28] = [
+    0xe0, 0x30, 0x25, 0x60, 0xce, 0xd2, 0xfd, 0xcb, 0xfc, 0xb2, 0x60, 0x26, 0x97, 0xdf, 0x97, 0x0c,
+    0xd0, 0xd6, 0xa3, 0x8f, 0x94, 0xb3, 0x27, 0x03, 0xf5, 0x1c, 0x31, 0x2b,
+];
+
 impl Network {
-    /// Get the UNIX time associated with a slot
-    pub fn to_unix_time(&self, slot: u64) -> u64 {
-        let offset = match self {
-            Self::Preview => 1666656000,
-            Self::Mainnet => 1591566291,
+    /// The hash of the Sundae v3 order script, if we know it for this network.
+    pub fn order_script_hash(&self) -> Option<&[u8]> {
+        match self {
+            Self::Custom(custom) => custom.order_script_hash.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The hash of the Sundae v3 pool script, if we know it for this network.
+    pub fn pool_script_hash(&self) -> Option<&[u8]> {
+        match self {
+            Self::Mainnet => Some(&MAINNET_POOL_SCRIPT_HASH),
+            Self::Custom(custom) => custom.pool_script_hash.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Where the Sundae v3 order and pool scripts live on this network, if we know both. The
+    /// order addresses on mainnet, and both on preview and preprod, aren't built in yet, so use
+    /// a custom network or [`Strategy::watch_addresses`] to list them.
+    pub fn sundae_addresses(&self) -> Option<SundaeAddresses> {
+        let addresses = match self {
+            Self::Mainnet => SundaeAddresses {
+                orders: vec![],
+                pools: vec![
+                    pallas_addresses::Address::from_bech32(MAINNET_POOL_ADDRESS)
+                        .expect("the mainnet pool address is valid")
+                        .to_vec(),
+                ],
+            },
+            Self::Preview | Self::Preprod => return None,
+            Self::Custom(custom) => SundaeAddresses {
+                orders: custom.order_addresses.clone(),
+                pools: custom.pool_addresses.clone(),
+            },
         };
-        (slot + offset) * 1000
+        (!addresses.orders.is_empty() && !addresses.pools.is_empty()).then_some(addresses)
+    }
+
+    /// The ADA deposit held in every Sundae v3 order, in lovelace.
+    pub fn order_deposit(&self) -> u64 {
+        match self {
+            Self::Custom(custom) => custom.order_deposit,
+            _ => ORDER_DEPOSIT_LOVELACE,
+        }
     }
 
-    pub(crate) fn relay_url(&self) -> Url {
+    pub(crate) fn relay_url(&self) -> Option<Url> {
         let url = match self {
             Self::Preview => "http://sse-relay.preview.sundae.fi/publish",
             Self::Mainnet => "http://sse-relay.sundae.fi/publish",
+            Self::Preprod => return None,
+            Self::Custom(custom) => return custom.relay_url.clone(),
         };
-        Url::parse(url).unwrap()
+        Some(Url::parse(url).unwrap())
     }
 }
 
+/// Addresses of the Sundae v3 scripts on a network, which a strategy watches instead of every
+/// output on chain.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SundaeAddresses {
+    /// Addresses holding orders. Orders are usually placed under their owner's staking
+    /// credential, so list every variant of the order script address you want to see.
+    pub orders: Vec<Vec<u8>>,
+    /// Addresses holding pools.
+    pub pools: Vec<Vec<u8>>,
+}
+
+/// The ADA deposit held in a Sundae v3 order unless the network says otherwise, which is returned
+/// with the order's output.
+pub const ORDER_DEPOSIT_LOVELACE: u64 = 2_000_000;
+
 /// Information about a strategy order getting managed by this library.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ManagedStrategy {
@@ -61,8 +218,42 @@ pub struct ManagedStrategy {
     pub order: OrderDatum,
 }
 
+impl ManagedStrategy {
+    /// The identifier of the pool this order must execute against, if it's bound to one.
+    pub fn pool_ident(&self) -> Option<&[u8]> {
+        self.order.pool_ident.as_deref()
+    }
+
+    /// Whether this order can execute against the given pool: either it's bound to that pool,
+    /// or it isn't bound to any pool.
+    pub fn accepts_pool(&self, pool_ident: &[u8]) -> bool {
+        self.pool_ident().is_none_or(|ident| ident == pool_ident)
+    }
+
+    /// Everything held in the order's UTXO.
+    pub fn total_value(&self) -> Value {
+        Value::from(&self.utxo)
+    }
+
+    /// The most ADA the scooper may take from this order as a fee.
+    pub fn max_protocol_fee(&self) -> Option<u64> {
+        types::to_u64(&self.order.max_protocol_fee)
+    }
+
+    /// What the order can actually trade: everything it holds, minus the scooper fee and the
+    /// network's ADA deposit which gets returned with the order's output. None if the order holds
+    /// too little ADA to cover both.
+    pub fn usable_value(&self, network: &Network) -> Option<Value> {
+        let reserved = Value::from_lovelace(
+            self.max_protocol_fee()?
+                .checked_add(network.order_deposit())?,
+        );
+        self.total_value().checked_sub(&reserved)
+    }
+}
+
 /// Information about a Sundae pool
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PoolState {
     /// The slot in which we first saw the pool.
     pub slot: u64,
@@ -82,6 +273,9 @@ impl<T> Clone for NewStrategyHandler<T> {
     }
 }
 
+/// Decides whether the strategy cares about updates to a pool.
+pub type PoolFilter<T> = fn(&Config<T>, &PoolState) -> bool;
+
 pub type NewPoolStateCallback<T> =
     fn(&Config<T>, &PoolState, &Vec<ManagedStrategy>) -> WorkerResult<Ack>;
 struct NewPoolStateHandler<T>(Option<NewPoolStateCallback<T>>);
@@ -99,18 +293,108 @@ impl<T> Clone for EachTxHandler<T> {
     }
 }
 
+/// Why a managed strategy order was spent.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpendReason {
+    /// A scooper executed the order against a pool.
+    Executed,
+    /// The owner cancelled the order and reclaimed the funds.
+    Cancelled,
+    /// We couldn't tell from the transaction.
+    Unknown,
+}
+
+impl SpendReason {
+    /// Work out why the order at `output` was spent by `tx`, from the redeemer it was spent with,
+    /// falling back to whether the transaction produced a new pool state.
+    pub fn classify(tx: &Tx, output: &OutputReference) -> Self {
+        let input = tx.tx.inputs.iter().find(|input| {
+            input.tx_hash.as_ref() == output.transaction_id.0.as_slice()
+                && input.output_index as u64 == output.output_index
+        });
+        let redeemer = input
+            .and_then(|input| input.redeemer.as_ref())
+            .and_then(|redeemer| types::try_parse::<OrderRedeemer>(&redeemer.original_cbor));
+        match redeemer {
+            Some(OrderRedeemer::Scoop) => return Self::Executed,
+            Some(OrderRedeemer::Cancel) => return Self::Cancelled,
+            None => {}
+        }
+
+        let creates_pool = tx.tx.outputs.iter().any(|output| {
+            output
+                .datum
+                .as_ref()
+                .and_then(|d| types::try_parse::<PoolDatum>(&d.original_cbor))
+                .is_some()
+        });
+        if creates_pool {
+            Self::Executed
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+pub type StrategySpentCallback<T> =
+    fn(&Config<T>, &ManagedStrategy, &Tx, SpendReason) -> WorkerResult<Ack>;
+struct StrategySpentHandler<T>(Option<StrategySpentCallback<T>>);
+impl<T> Clone for StrategySpentHandler<T> {
+    fn clone(&self) -> Self {
+        Self(self.0)
+    }
+}
+
+/// The changes undone when the chain rolled back.
+#[derive(Debug, Clone)]
+pub struct Rollback {
+    /// The slot we rolled back to; everything we saw at or after this slot was undone.
+    pub slot: u64,
+    /// Orders we had seen get spent, which are under our custody again.
+    pub restored: Vec<ManagedStrategy>,
+    /// Orders we had started tracking, which no longer exist.
+    pub discarded: Vec<ManagedStrategy>,
+}
+
+pub type RollbackCallback<T> = fn(&Config<T>, &Rollback) -> WorkerResult<Ack>;
+struct RollbackHandler<T>(Option<RollbackCallback<T>>);
+impl<T> Clone for RollbackHandler<T> {
+    fn clone(&self) -> Self {
+        Self(self.0)
+    }
+}
+
 /// The entry point to a Sundae strategy worker. Use this to register handlers for interesting events.
 pub struct Strategy<T> {
+    confirmations: u64,
+    pool_filter: Option<PoolFilter<T>>,
+    watched: Option<SundaeAddresses>,
+    signing_keys: Vec<String>,
+    authorized_scripts: Vec<Vec<u8>>,
     new_strategy_callback: NewStrategyHandler<T>,
+    tentative_strategy_callback: NewStrategyHandler<T>,
     new_pool_state_callback: NewPoolStateHandler<T>,
+    tentative_pool_state_callback: NewPoolStateHandler<T>,
     each_tx_callback: EachTxHandler<T>,
+    strategy_spent_callback: StrategySpentHandler<T>,
+    rollback_callback: RollbackHandler<T>,
 }
 impl<T> Clone for Strategy<T> {
     fn clone(&self) -> Self {
         Self {
+            confirmations: self.confirmations,
+            pool_filter: self.pool_filter,
+            watched: self.watched.clone(),
+            signing_keys: self.signing_keys.clone(),
+            authorized_scripts: self.authorized_scripts.clone(),
             each_tx_callback: self.each_tx_callback.clone(),
+            strategy_spent_callback: self.strategy_spent_callback.clone(),
+            rollback_callback: self.rollback_callback.clone(),
             new_pool_state_callback: self.new_pool_state_callback.clone(),
+            tentative_pool_state_callback: self.tentative_pool_state_callback.clone(),
             new_strategy_callback: self.new_strategy_callback.clone(),
+            tentative_strategy_callback: self.tentative_strategy_callback.clone(),
         }
     }
 }
@@ -121,35 +405,176 @@ where
 {
     pub fn new() -> Self {
         Strategy {
+            confirmations: 0,
+            pool_filter: None,
+            watched: None,
+            signing_keys: vec![STRATEGY_KEY.to_string()],
+            authorized_scripts: vec![],
             new_strategy_callback: NewStrategyHandler(None),
+            tentative_strategy_callback: NewStrategyHandler(None),
             new_pool_state_callback: NewPoolStateHandler(None),
+            tentative_pool_state_callback: NewPoolStateHandler(None),
             each_tx_callback: EachTxHandler(None),
+            strategy_spent_callback: StrategySpentHandler(None),
+            rollback_callback: RollbackHandler(None),
         }
     }
 
+    /// Wait until events are buried under `n` more blocks before running the `on_new_strategy`
+    /// and `on_new_pool_state` callbacks, so they don't act on blocks which get rolled back.
+    ///
+    /// Depth is counted in blocks observed by this worker.
+    pub fn with_confirmations(mut self, n: u64) -> Self {
+        self.confirmations = n;
+        self
+    }
+
+    /// Only run pool callbacks for pools which pass this filter.
+    pub fn with_pool_filter(mut self, f: PoolFilter<T>) -> Self {
+        self.pool_filter = Some(f);
+        self
+    }
+
+    /// Only look at outputs at these Sundae v3 order and pool addresses, and at the addresses of
+    /// the orders we were tracking when the worker started, instead of trying to decode the datum
+    /// of every output on chain.
+    ///
+    /// Every transaction is still delivered, so spends of our orders are seen wherever they pay.
+    pub fn watch_addresses(mut self, addresses: SundaeAddresses) -> Self {
+        self.watched = Some(addresses);
+        self
+    }
+
+    /// Watch the Sundae v3 addresses of the given network; see [`Strategy::watch_addresses`].
+    /// Fails if the network doesn't know where both scripts live, rather than silently watching
+    /// nothing.
+    pub fn watch_network(self, network: &Network) -> WorkerResult<Self> {
+        let addresses = network.sundae_addresses().ok_or_else(|| {
+            let name = match network {
+                Network::Preview => "preview",
+                Network::Preprod => "preprod",
+                Network::Mainnet => "mainnet",
+                Network::Custom(_) => "this custom network",
+            };
+            Error::Internal(format!(
+                "the Sundae v3 order and pool addresses on {name} aren't known"
+            ))
+        })?;
+        Ok(self.watch_addresses(addresses))
+    }
+
+    /// Claim strategy orders signed by any of these keys, instead of only the `default` key. Each
+    /// key is registered as an ed25519 signer for this worker, and the first one signs executions
+    /// of orders we don't know the signer of. The `default` key stays registered either way.
+    pub fn with_signing_keys(mut self, names: &[&str]) -> Self {
+        self.signing_keys = names.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// Also claim strategy orders authorized by the given stake script.
+    pub fn claim_script(mut self, script_hash: Vec<u8>) -> Self {
+        self.authorized_scripts.push(script_hash);
+        self
+    }
+
     /// Register a callback to run when a new strategy is seen.
     pub fn on_new_strategy(mut self, f: NewStrategyCallback<T>) -> Self {
         self.new_strategy_callback = NewStrategyHandler(Some(f));
         self
     }
-    /// Register a callback to run when a Sundae pool has been updated.
+    /// Register a callback to run as soon as a new strategy is seen, without waiting for confirmations.
+    pub fn on_tentative_strategy(mut self, f: NewStrategyCallback<T>) -> Self {
+        self.tentative_strategy_callback = NewStrategyHandler(Some(f));
+        self
+    }
+    /// Register a callback to run when a Sundae pool has been updated. The callback is given the
+    /// managed strategies which can execute against that pool.
     pub fn on_new_pool_state(mut self, f: NewPoolStateCallback<T>) -> Self {
         self.new_pool_state_callback = NewPoolStateHandler(Some(f));
         self
     }
-    /// Register a callback to run for every transaction.
+    /// Register a callback to run as soon as a Sundae pool has been updated, without waiting for confirmations.
+    pub fn on_tentative_pool_state(mut self, f: NewPoolStateCallback<T>) -> Self {
+        self.tentative_pool_state_callback = NewPoolStateHandler(Some(f));
+        self
+    }
+    /// Register a callback to run for every transaction. Use [`pools::latest`] or
+    /// [`pools::for_pair`] to look up current prices.
     pub fn on_each_tx(mut self, f: EachTxCallback<T>) -> Self {
         self.each_tx_callback = EachTxHandler(Some(f));
         self
     }
+    /// Register a callback to run when a managed strategy order is spent, either because it was
+    /// executed or because its owner cancelled it.
+    pub fn on_strategy_spent(mut self, f: StrategySpentCallback<T>) -> Self {
+        self.strategy_spent_callback = StrategySpentHandler(Some(f));
+        self
+    }
+    /// Register a callback to run when the chain rolls back. Managed orders are reverted
+    /// automatically; use this to revert any state of your own.
+    pub fn on_rollback(mut self, f: RollbackCallback<T>) -> Self {
+        self.rollback_callback = RollbackHandler(Some(f));
+        self
+    }
 
     /// Finish building this strategy handler and construct a Balius worker.
     pub fn worker(self) -> Worker {
-        Worker::new()
-            .with_request_handler("get-signer-key", self.clone())
-            .with_utxo_handler(UtxoMatcher::all(), self.clone())
+        keys::set_signing_keys(self.signing_keys.clone());
+        let mut signers = self.signing_keys.clone();
+        if !signers.iter().any(|name| name == STRATEGY_KEY) {
+            signers.push(STRATEGY_KEY.to_string());
+        }
+        let worker = signers.iter().fold(
+            Worker::new()
+                .with_request_handler("get-signer-key", self.clone())
+                .with_request_handler("get-executions", GetExecutions),
+            |worker, name| worker.with_signer(name, "ed25519"),
+        );
+
+        // Balius matches outputs by their exact address. Spends and rollbacks aren't tied to the
+        // addresses a transaction pays to, so transactions are always delivered; they're cheap to
+        // handle, since only their inputs are looked up
+        let worker = worker
             .with_tx_handler(UtxoMatcher::all(), self.clone())
-            .with_signer(STRATEGY_KEY, "ed25519")
+            .with_tx_undo_handler(UtxoMatcher::all(), self.clone());
+        let Some(addresses) = self.utxo_addresses() else {
+            warn!("no Sundae addresses to watch, so the datum of every output will be decoded");
+            return worker.with_utxo_handler(UtxoMatcher::all(), self.clone());
+        };
+        addresses.into_iter().fold(worker, |worker, address| {
+            worker.with_utxo_handler(UtxoMatcher::by_address(address), self.clone())
+        })
+    }
+
+    /// Every address whose outputs we need to see, or None to see every output.
+    fn utxo_addresses(&self) -> Option<Vec<Vec<u8>>> {
+        let watched = self.watched.as_ref()?;
+        let mut addresses: Vec<Vec<u8>> = watched
+            .orders
+            .iter()
+            .chain(&watched.pools)
+            .cloned()
+            .collect();
+        // Orders we're already tracking may sit at an address we weren't told about, and get
+        // paid back to it when they're executed
+        match orders::all() {
+            Ok(orders) => addresses.extend(orders.into_iter().map(|o| o.utxo.address.to_vec())),
+            Err(err) => warn!("couldn't list tracked orders to watch: {err:?}"),
+        }
+        addresses.sort();
+        addresses.dedup();
+        Some(addresses)
+    }
+
+    /// Whether an output we've been given might hold a pool, and whether it might hold an order.
+    fn may_hold(&self, address: &[u8]) -> (bool, bool) {
+        match &self.watched {
+            Some(watched) => {
+                let pool = watched.pools.iter().any(|a| a == address);
+                (pool, !pool)
+            }
+            None => (true, true),
+        }
     }
 }
 
@@ -173,7 +598,11 @@ where
     ) -> Result<wit::Response, wit::HandleError> {
         let config: Config<T> = config.try_into()?;
 
-        let result = if let Ok(tx) = event.clone().try_into() {
+        let result = if let wit::Event::TxUndo(undo) = event {
+            // An undone tx carries the same payload as a tx, so reuse its conversion
+            let tx = wit::Event::Tx(undo).try_into()?;
+            self.handle_undo_tx(config, tx)
+        } else if let Ok(tx) = event.clone().try_into() {
             self.handle_tx(config, tx)
         } else if let Ok(utxo) = event.clone().try_into() {
             self.handle_utxo(config, utxo)
@@ -184,6 +613,12 @@ where
             Ok(Ack)
         };
 
+        if let Err(err) = &result {
+            events::emit(&StrategyEvent::HandlerError {
+                message: format!("{err:?}"),
+            });
+        }
+
         Ok(result?.try_into()?)
     }
 }
@@ -206,31 +641,43 @@ impl<T: Send + Sync + 'static> Strategy<T> {
             "transaction output is a sundae v3 order",
         );
 
-        let Some(key) = balius_sdk::get_public_keys().remove(STRATEGY_KEY) else {
-            return Err(Error::Internal("key not found".into()));
-        };
-
         // Check if it's *our* order
-        use StrategyAuthorization::Signature;
-        match &datum.details {
-            Order::Strategy {
-                auth: Signature { signer },
-            } => {
-                if signer == &key {
+        let Order::Strategy { auth } = &datum.details else {
+            return Ok(Ack);
+        };
+        match auth {
+            StrategyAuthorization::Signature { signer } => {
+                let public_keys = balius_sdk::get_public_keys();
+                let owner = self
+                    .signing_keys
+                    .iter()
+                    .find(|name| public_keys.get(*name) == Some(signer));
+                let Some(owner) = owner else {
                     info!(
-                        "transaction output is a strategy order owned by us ({})",
+                        "transaction output is a strategy order signed by {}, which isn't one of our keys",
                         hex::encode(signer)
                     );
-                } else {
+                    return Ok(Ack);
+                };
+                info!(
+                    "transaction output is a strategy order owned by our key {} ({})",
+                    owner,
+                    hex::encode(signer)
+                );
+            }
+            StrategyAuthorization::Script { script } => {
+                if !self.authorized_scripts.contains(script) {
                     info!(
-                        "transaction output is a strategy order not owned by ({}), not us ({})",
-                        hex::encode(signer),
-                        hex::encode(&key)
+                        "transaction output is a strategy order authorized by script {}, which we don't claim",
+                        hex::encode(script)
                     );
                     return Ok(Ack);
                 }
+                info!(
+                    "transaction output is a strategy order authorized by our script {}",
+                    hex::encode(script)
+                );
             }
-            _ => return Ok(Ack),
         }
 
         info!(
@@ -250,16 +697,41 @@ impl<T: Send + Sync + 'static> Strategy<T> {
             order: datum,
         };
 
-        // This is an order "under our custody", so we hold onto it
-        let mut all_seen: Vec<ManagedStrategy> = kv::get(KV_MANAGED_ORDERS)?.unwrap_or_default();
+        // This is an order "under our custody", so we hold onto it. If we're replaying blocks
+        // we've already processed, we may be tracking it already.
+        if !orders::insert(&seen)? {
+            info!(
+                tx_ref = seen.output.to_string(),
+                "already tracking this order, ignoring"
+            );
+            return Ok(Ack);
+        }
+        journal::record(seen.slot, Change::OrderAdded(seen.output.clone()))?;
 
-        all_seen.push(seen.clone());
-        kv::set(KV_MANAGED_ORDERS, &all_seen)?;
+        info!("now tracking {} orders", orders::count()?);
 
-        info!("now tracking {} orders", all_seen.len());
+        events::emit(&StrategyEvent::OrderObserved {
+            slot: seen.slot,
+            output: seen.output.to_string(),
+        });
 
+        if let NewStrategyHandler(Some(callback)) = self.tentative_strategy_callback {
+            callback(config, &seen)?;
+        }
+        if self.confirmations > 0 {
+            confirmations::defer(seen.slot, PendingEvent::NewStrategy(seen))?;
+            Ok(Ack)
+        } else {
+            self.fire_new_strategy(config, &seen)
+        }
+    }
+    fn fire_new_strategy(
+        &self,
+        config: &Config<T>,
+        strategy: &ManagedStrategy,
+    ) -> WorkerResult<Ack> {
         if let NewStrategyHandler(Some(callback)) = self.new_strategy_callback {
-            callback(config, &seen)
+            callback(config, strategy)
         } else {
             Ok(Ack)
         }
@@ -291,15 +763,70 @@ impl<T: Send + Sync + 'static> Strategy<T> {
             pool_datum: datum,
         };
 
-        let all_seen: Vec<ManagedStrategy> = kv::get(KV_MANAGED_ORDERS)?.unwrap_or_default();
+        if let Some(filter) = self.pool_filter
+            && !filter(config, &pool_state)
+        {
+            trace!("pool is filtered out, ignoring");
+            return Ok(Ack);
+        }
+
+        // Keep the latest state of each pool around, for callbacks which need a price
+        if !pools::update(&pool_state)? {
+            trace!("already seen a newer state for this pool, ignoring");
+            return Ok(Ack);
+        }
 
+        if let NewPoolStateHandler(Some(callback)) = self.tentative_pool_state_callback {
+            callback(config, &pool_state, &strategies_for_pool(&pool_state)?)?;
+        }
+        if self.confirmations > 0 {
+            confirmations::defer(pool_state.slot, PendingEvent::NewPoolState(pool_state))?;
+            Ok(Ack)
+        } else {
+            self.fire_new_pool_state(config, &pool_state)
+        }
+    }
+    fn fire_new_pool_state(&self, config: &Config<T>, pool_state: &PoolState) -> WorkerResult<Ack> {
         if let NewPoolStateHandler(Some(callback)) = self.new_pool_state_callback {
-            callback(config, &pool_state, &all_seen)
+            callback(config, pool_state, &strategies_for_pool(pool_state)?)
         } else {
             Ok(Ack)
         }
     }
 
+    /// Note which block we're in, and run any callbacks which now have enough confirmations.
+    fn advance(&self, config: &Config<T>, slot: u64) -> WorkerResult<()> {
+        if self.confirmations == 0 || !confirmations::observe_block(slot)? {
+            return Ok(());
+        }
+        // Events stay pending until their callback succeeds; ones which fail are retried on the
+        // next block, until they've failed too many times
+        for (id, event) in confirmations::confirmed(self.confirmations)? {
+            let result = match event {
+                PendingEvent::NewStrategy(strategy) => {
+                    // Skip orders which were spent while we waited
+                    match orders::get(&strategy.output)? {
+                        Some(_) => self.fire_new_strategy(config, &strategy),
+                        None => Ok(Ack),
+                    }
+                }
+                PendingEvent::NewPoolState(pool_state) => {
+                    self.fire_new_pool_state(config, &pool_state)
+                }
+            };
+            match result {
+                Ok(_) => confirmations::complete(id)?,
+                Err(err) => {
+                    events::emit(&StrategyEvent::HandlerError {
+                        message: format!("{err:?}"),
+                    });
+                    confirmations::fail(id)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn handle_utxo(&self, config: Config<T>, utxo: Utxo<()>) -> WorkerResult<Ack> {
         trace!(
             slot = utxo.block_slot,
@@ -307,8 +834,15 @@ impl<T: Send + Sync + 'static> Strategy<T> {
             "transaction output observed",
         );
 
-        self.handle_pool_state(&config, &utxo)?;
-        self.handle_strategy_order(&config, &utxo)?;
+        executions::observe_slot(utxo.block_slot);
+        self.advance(&config, utxo.block_slot)?;
+        let (pool, order) = self.may_hold(&utxo.utxo.address);
+        if pool {
+            self.handle_pool_state(&config, &utxo)?;
+        }
+        if order {
+            self.handle_strategy_order(&config, &utxo)?;
+        }
         Ok(Ack)
     }
 
@@ -318,39 +852,147 @@ impl<T: Send + Sync + 'static> Strategy<T> {
             tx_hash = hex::encode(&tx.hash),
             "transaction observed",
         );
-        let spent_inputs = tx
-            .tx
-            .inputs
-            .iter()
-            .map(|input| (input.tx_hash.to_vec(), input.output_index as u64))
-            .collect::<Vec<_>>();
+        executions::observe_slot(tx.block_slot);
+        self.advance(&config, tx.block_slot)?;
 
         trace!("Marking orders as spent, if any...");
-        let mut seen_orders: Vec<ManagedStrategy> = kv::get(KV_MANAGED_ORDERS)?.unwrap_or_default();
+        for input in &tx.tx.inputs {
+            let spent = OutputReference {
+                transaction_id: TransactionId(input.tx_hash.to_vec()),
+                output_index: input.output_index as u64,
+            };
+            let Some(order) = orders::remove(&spent)? else {
+                continue;
+            };
+            journal::record(tx.block_slot, Change::OrderRemoved(order.clone()))?;
 
-        seen_orders.retain(|spent| {
-            let (spent_hash, spent_index) =
-                (&spent.output.transaction_id.0, &spent.output.output_index);
-            !spent_inputs
-                .iter()
-                .any(|(hash, index)| spent_hash == hash && spent_index == index)
-        });
+            let reason = SpendReason::classify(&tx, &spent);
+            info!(
+                tx_ref = spent.to_string(),
+                ?reason,
+                "managed strategy order spent"
+            );
+            events::emit(&StrategyEvent::OrderSpent {
+                slot: tx.block_slot,
+                output: spent.to_string(),
+                tx_hash: hex::encode(&tx.hash),
+                reason,
+            });
+            executions::record_spend(
+                &spent,
+                Some(OrderSpend {
+                    slot: tx.block_slot,
+                    tx_hash: hex::encode(&tx.hash),
+                    reason,
+                }),
+            )?;
+            if let StrategySpentHandler(Some(callback)) = self.strategy_spent_callback {
+                callback(&config, &order, &tx, reason)?;
+            }
+        }
 
-        kv::set(KV_MANAGED_ORDERS, &seen_orders)?;
+        if let EachTxHandler(Some(callback)) = self.each_tx_callback {
+            callback(&config, &tx, &orders::all()?)
+        } else {
+            Ok(Ack)
+        }
+    }
 
-        trace!("remaining orders: {:?}", seen_orders);
+    fn handle_undo_tx(&self, config: Config<T>, tx: Tx) -> WorkerResult<Ack> {
+        executions::observe_slot(tx.block_slot);
+        confirmations::discard_from(tx.block_slot)?;
+        let changes = journal::rollback(tx.block_slot)?;
+        if changes.is_empty() {
+            return Ok(Ack);
+        }
+        info!(slot = tx.block_slot, "chain rolled back, reverting changes");
 
-        if let EachTxHandler(Some(callback)) = self.each_tx_callback {
-            callback(&config, &tx, &seen_orders)
+        let mut rollback = Rollback {
+            slot: tx.block_slot,
+            restored: vec![],
+            discarded: vec![],
+        };
+        let mut snapshots = vec![];
+        for change in changes {
+            match change {
+                Change::OrderAdded(output) => {
+                    executions::forget(&output)?;
+                    if let Some(order) = orders::remove(&output)? {
+                        rollback.discarded.push(order);
+                    }
+                }
+                Change::OrderRemoved(order) => {
+                    executions::record_spend(&order.output, None)?;
+                    if orders::insert(&order)? {
+                        rollback.restored.push(order);
+                    }
+                }
+                Change::PoolUpdated { ident, previous } => {
+                    pools::restore(&ident, previous.as_deref())?;
+                    snapshots.extend(previous);
+                }
+                Change::BlockObserved(cursor) => confirmations::restore_cursor(cursor)?,
+            }
+        }
+        // Several updates in a slot share a snapshot, so only remove them once they're all undone
+        for snapshot in snapshots {
+            kv::remove(&snapshot)?;
+        }
+
+        events::emit(&StrategyEvent::RolledBack {
+            slot: rollback.slot,
+            restored: rollback
+                .restored
+                .iter()
+                .map(|o| o.output.to_string())
+                .collect(),
+            discarded: rollback
+                .discarded
+                .iter()
+                .map(|o| o.output.to_string())
+                .collect(),
+        });
+
+        if let RollbackHandler(Some(callback)) = self.rollback_callback {
+            callback(&config, &rollback)
         } else {
             Ok(Ack)
         }
     }
 }
 
-pub(crate) const KV_MANAGED_ORDERS: &str = "managed_orders";
+/// The managed strategies which can execute against the given pool.
+fn strategies_for_pool(pool_state: &PoolState) -> WorkerResult<Vec<ManagedStrategy>> {
+    let ident = &pool_state.pool_datum.identifier;
+    Ok(orders::all()?
+        .into_iter()
+        .filter(|strategy| strategy.accepts_pool(ident))
+        .collect())
+}
+
 pub(crate) const STRATEGY_KEY: &str = "default";
 
+/// Sign an execution with whichever of our keys authorizes the order. Orders authorized by a
+/// script don't need a signature, and orders we don't know about are signed with the first
+/// configured key.
+fn sign_execution(utxo: &OutputReference, bytes: &[u8]) -> WorkerResult<Option<Vec<u8>>> {
+    let key = match orders::get(utxo)?.map(|order| order.order.details) {
+        Some(Order::Strategy {
+            auth: StrategyAuthorization::Script { .. },
+        }) => return Ok(None),
+        Some(Order::Strategy {
+            auth: StrategyAuthorization::Signature { signer },
+        }) => balius_sdk::get_public_keys()
+            .into_iter()
+            .find(|(_, key)| key == &signer)
+            .map(|(name, _)| name)
+            .unwrap_or_else(|| keys::primary_key().to_string()),
+        _ => keys::primary_key().to_string(),
+    };
+    let signature = balius_sdk::wit::balius::app::sign::sign_payload(&key, bytes)?;
+    Ok(Some(signature))
+}
+
 /// Submit a strategy execution.
 ///
 /// # Examples
@@ -397,7 +1039,28 @@ pub fn submit_execution(
     utxo: &OutputReference,
     validity_range: Interval,
     details: Order,
-) -> Result<HttpResponse, Error> {
+) -> Result<SubmitOutcome, Error> {
+    submit_execution_with(&Submitter::Default, network, utxo, validity_range, details)
+}
+
+/// Submit a strategy execution through the given submitter, such as one chosen in the worker
+/// config.
+///
+/// Submitting the same order and window again is a no-op once it has been accepted, so on a
+/// [`SubmitOutcome::Failed`] outcome, callbacks can simply submit it again on a later event.
+pub fn submit_execution_with(
+    submitter: &Submitter,
+    network: &Network,
+    utxo: &OutputReference,
+    validity_range: Interval,
+    details: Order,
+) -> Result<SubmitOutcome, Error> {
+    let window = validity_range.bounds();
+    if executions::already_submitted(utxo, window)? {
+        info!("already submitted an execution of {utxo} for this window, skipping");
+        return Ok(SubmitOutcome::Duplicate);
+    }
+
     let execution = StrategyExecution {
         tx_ref: utxo.clone(),
         validity_range,
@@ -407,11 +1070,13 @@ pub fn submit_execution(
 
     let bytes = serialize(execution.clone());
 
-    let signature = balius_sdk::wit::balius::app::sign::sign_payload("default", &bytes)?;
+    let signature = sign_execution(utxo, &bytes)?;
+    let record_signature = signature.as_ref().map(hex::encode);
+    let record_details = execution.details.clone();
 
     let sse = SignedStrategyExecution {
         execution,
-        signature: Some(signature),
+        signature,
     };
     let sse_bytes = serialize(sse);
 
@@ -421,15 +1086,219 @@ pub fn submit_execution(
         data: hex::encode(&sse_bytes),
     };
 
-    info!(
-        "posting to {}: {} / {} / {}",
-        network.relay_url(),
-        hex::encode(&utxo.transaction_id.0),
-        utxo.output_index,
-        hex::encode(&sse_bytes)
+    let outcome = submitter.submit(network, utxo, window, &submit_sse)?;
+    executions::record_execution(
+        utxo,
+        ExecutionRecord::new(
+            network,
+            window,
+            record_details,
+            record_signature,
+            outcome.clone(),
+        ),
+    )?;
+
+    events::emit(&StrategyEvent::ExecutionSubmitted {
+        output: utxo.to_string(),
+        outcome: outcome.clone(),
+    });
+
+    Ok(outcome)
+}
+
+/// The test pool from [`types::test_pool`], as seen in the given slot with the given ADA reserve.
+#[cfg(test)]
+pub(crate) fn test_pool_state(slot: u64, lovelace: u64) -> PoolState {
+    let (pool_datum, mut utxo) = types::test_pool();
+    utxo.coin = lovelace;
+    PoolState {
+        slot,
+        output: types::test_output(slot as u8),
+        utxo,
+        pool_datum,
+    }
+}
+
+/// A strategy order held in the first output of a made-up transaction.
+#[cfg(test)]
+pub(crate) fn test_strategy(id: u8) -> ManagedStrategy {
+    use crate::types::{Destination, MultisigScript};
+
+    ManagedStrategy {
+        slot: 100,
+        output: types::test_output(id),
+        utxo: TxOutput {
+            coin: 10_000_000,
+            ..Default::default()
+        },
+        order: OrderDatum {
+            pool_ident: None,
+            owner: MultisigScript::Signature {
+                key_hash: vec![id; 28],
+            },
+            max_protocol_fee: types::parse(&hex::decode("1a000f4240").unwrap()).unwrap(),
+            destination: Destination::Self_,
+            details: Order::Strategy {
+                auth: StrategyAuthorization::Signature {
+                    signer: vec![id; 32],
+                },
+            },
+            extra: vec![],
+        },
+    }
+}
+
+#[test]
+pub fn test_preview_network() {
+    let network = Network::Preview;
+    assert_eq!(network.order_script_hash(), None);
+    assert_eq!(network.pool_script_hash(), None);
+    assert_eq!(network.sundae_addresses(), None);
+    assert!(network.relay_url().is_some());
+    assert!(
+        Strategy::<serde_json::Value>::new()
+            .watch_network(&network)
+            .is_err()
+    );
+}
+
+#[test]
+pub fn test_preprod_network() {
+    let network = Network::Preprod;
+    assert_eq!(network.order_script_hash(), None);
+    assert_eq!(network.pool_script_hash(), None);
+    assert_eq!(network.sundae_addresses(), None);
+    assert_eq!(network.relay_url(), None);
+    assert!(
+        Strategy::<serde_json::Value>::new()
+            .watch_network(&network)
+            .is_err()
+    );
+}
+
+#[test]
+pub fn test_mainnet_network() {
+    let network = Network::Mainnet;
+    // addr1x8srqftqemf0mjlukfszd97ljuxdp44r372txfcr75wrz26rnxqnmtv3hdu2t6chcfhl2zzjh36a87nmd6dwsu3jenqsslnz7e
+    let pool_address = hex::decode("31e0302560ced2fdcbfcb2602697df970cd0d6a38f94b32703f51c312b4399813dad91bb78a5eb17c26ff50852bc75d3fa7b6e9ae87232ccc1").unwrap();
+    assert_eq!(payment_script(&pool_address), network.pool_script_hash());
+    assert_eq!(network.order_script_hash(), None);
+    // We know where pools live, but not orders, so there's nothing complete to watch
+    assert_eq!(network.sundae_addresses(), None);
+    assert!(
+        Strategy::<serde_json::Value>::new()
+            .watch_network(&network)
+            .is_err()
+    );
+
+    // Key payment credentials and Byron addresses never match a script
+    let key_address =
+        hex::decode("61e0302560ced2fdcbfcb2602697df970cd0d6a38f94b32703f51c312b").unwrap();
+    assert_eq!(payment_script(&key_address), None);
+    assert_eq!(payment_script(&[0x82]), None);
+}
+
+#[test]
+pub fn test_custom_network() {
+    let network: Network = serde_json::from_str(
+        r#"{ "custom": {
+            "system_start": 1666656000,
+            "order_script_hash": "c37b1b5dc0669f1d3c61a6fddb2e8fde96be87b881c60bce8e8d542f",
+            "order_addresses": ["addr1w8phkx6acpnf78fuvxn0mkew3l0fd058hzquvz7w36x4gtcyjy7wx"],
+            "pool_addresses": ["addr1x8srqftqemf0mjlukfszd97ljuxdp44r372txfcr75wrz26rnxqnmtv3hdu2t6chcfhl2zzjh36a87nmd6dwsu3jenqsslnz7e"]
+        } }"#,
+    )
+    .unwrap();
+    let addresses = network.sundae_addresses().unwrap();
+    assert_eq!(
+        payment_script(&addresses.orders[0]),
+        network.order_script_hash()
+    );
+    assert_eq!(
+        payment_script(&addresses.pools[0]),
+        Network::Mainnet.pool_script_hash()
+    );
+    assert_eq!(network.relay_url(), None);
+
+    let strategy = Strategy::<serde_json::Value>::new()
+        .watch_network(&network)
+        .unwrap();
+    assert_eq!(strategy.watched, Some(addresses));
+
+    // A custom network which only knows its pools can't be watched
+    let network: Network = serde_json::from_str(
+        r#"{ "custom": {
+            "system_start": 1666656000,
+            "pool_addresses": ["addr1x8srqftqemf0mjlukfszd97ljuxdp44r372txfcr75wrz26rnxqnmtv3hdu2t6chcfhl2zzjh36a87nmd6dwsu3jenqsslnz7e"]
+        } }"#,
+    )
+    .unwrap();
+    assert!(
+        Strategy::<serde_json::Value>::new()
+            .watch_network(&network)
+            .is_err()
+    );
+    assert!(
+        serde_json::from_str::<Network>(
+            r#"{ "custom": { "system_start": 1666656000, "pool_addresses": ["not an address"] } }"#
+        )
+        .is_err()
+    );
+}
+
+#[test]
+pub fn test_watched_addresses() {
+    let watched = SundaeAddresses {
+        orders: vec![vec![0x71; 29]],
+        pools: vec![vec![0x31; 57]],
+    };
+    let unrelated = vec![0x61; 29];
+    let everything = Strategy::<serde_json::Value>::new();
+    assert_eq!(everything.utxo_addresses(), None);
+    assert_eq!(everything.may_hold(&unrelated), (true, true));
+
+    let strategy = Strategy::<serde_json::Value>::new().watch_addresses(watched.clone());
+    let tracked = test_strategy(1);
+    orders::insert(&ManagedStrategy {
+        utxo: TxOutput {
+            address: vec![0x11; 57].into(),
+            ..tracked.utxo.clone()
+        },
+        ..tracked
+    })
+    .unwrap();
+    let addresses = strategy.utxo_addresses().unwrap();
+    assert_eq!(
+        addresses,
+        vec![vec![0x11; 57], vec![0x31; 57], vec![0x71; 29]]
     );
+    // Outputs at other addresses aren't delivered at all
+    assert!(!addresses.contains(&unrelated));
+
+    assert_eq!(strategy.may_hold(&watched.pools[0]), (true, false));
+    assert_eq!(strategy.may_hold(&watched.orders[0]), (false, true));
+    assert_eq!(strategy.may_hold(&[0x11; 57]), (false, true));
+}
+
+#[test]
+pub fn test_usable_value() {
+    let order = test_strategy(1);
+    let ada = types::AssetId::from((vec![], vec![]));
+    // 10 ADA, less a 1 ADA fee and the 2 ADA deposit
+    let usable = order.usable_value(&Network::Mainnet).unwrap();
+    assert_eq!(usable.get(&ada), 7_000_000);
+
+    let network: Network = serde_json::from_str(
+        r#"{ "custom": { "system_start": 1666656000, "order_deposit": 3500000 } }"#,
+    )
+    .unwrap();
+    assert_eq!(network.order_deposit(), 3_500_000);
+    let usable = order.usable_value(&network).unwrap();
+    assert_eq!(usable.get(&ada), 5_500_000);
 
-    Ok(HttpRequest::post(network.relay_url())
-        .json(&submit_sse)?
-        .send()?)
+    let network: Network = serde_json::from_str(
+        r#"{ "custom": { "system_start": 1666656000, "order_deposit": 9000001 } }"#,
+    )
+    .unwrap();
+    assert!(order.usable_value(&network).is_none());
 }
@@ -0,0 +1,239 @@
+//! The strategy orders under our custody.
+//!
+//! Each order is stored under its own key, so checking whether an input spends one of our orders
+//! is a single lookup. Every tracked order also has a numbered entry in a dense index, for
+//! callbacks which need to see all of them; removing an order moves the last entry into its place,
+//! so neither adding nor removing an order rewrites the index. The list of every order is cached in
+//! memory until an order is added or removed, so callbacks which see every order don't read the
+//! whole store on each event.
+
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+};
+
+use balius_sdk::WorkerResult;
+use tracing::info;
+
+use crate::{ManagedStrategy, kv, types::OutputReference};
+
+/// Where older versions of this library stored every order, as a single JSON vector.
+const KV_LEGACY_MANAGED_ORDERS: &str = "managed_orders";
+/// How many orders are tracked, and so how many entries the index has.
+const KV_ORDER_COUNT: &str = "order_count";
+/// The index: the output reference of every tracked order, numbered from 0.
+const KV_ORDER_ENTRY_PREFIX: &str = "order_entry:";
+/// Which index entry refers to each order, so it can be removed.
+const KV_ORDER_ENTRY_ID_PREFIX: &str = "order_entry_id:";
+const KV_ORDER_PREFIX: &str = "order:";
+
+thread_local! {
+    static MIGRATED: Cell<bool> = const { Cell::new(false) };
+    static ALL: RefCell<Option<Vec<ManagedStrategy>>> = const { RefCell::new(None) };
+}
+
+fn order_key(output: &impl fmt::Display) -> String {
+    format!("{KV_ORDER_PREFIX}{output}")
+}
+
+/// Every strategy order currently under our custody.
+pub fn all() -> WorkerResult<Vec<ManagedStrategy>> {
+    if let Some(orders) = ALL.with_borrow(|cached| cached.clone()) {
+        return Ok(orders);
+    }
+    let mut orders = vec![];
+    for id in 0..count()? as u64 {
+        let Some(output) = kv::get::<String>(&entry_key(id))? else {
+            continue;
+        };
+        if let Some(order) = kv::get(&order_key(&output))? {
+            orders.push(order);
+        }
+    }
+    ALL.set(Some(orders.clone()));
+    Ok(orders)
+}
+
+/// Forget the cached list of orders, before changing what's in the store.
+fn invalidate() {
+    ALL.set(None);
+}
+
+/// How many orders are under our custody.
+pub fn count() -> WorkerResult<usize> {
+    migrate()?;
+    Ok(kv::get(KV_ORDER_COUNT)?.unwrap_or_default())
+}
+
+/// Look up a managed order by the UTXO holding it.
+pub fn get(output: &OutputReference) -> WorkerResult<Option<ManagedStrategy>> {
+    migrate()?;
+    kv::get(&order_key(output))
+}
+
+/// Start tracking an order. Returns false, and leaves the store untouched, if the order was
+/// already tracked.
+pub(crate) fn insert(order: &ManagedStrategy) -> WorkerResult<bool> {
+    if get(&order.output)?.is_some() {
+        return Ok(false);
+    }
+    invalidate();
+    add(order)?;
+    Ok(true)
+}
+
+/// Stop tracking an order, returning it if it was tracked.
+pub(crate) fn remove(output: &OutputReference) -> WorkerResult<Option<ManagedStrategy>> {
+    let Some(order) = get(output)? else {
+        return Ok(None);
+    };
+    invalidate();
+    let key = output.to_string();
+    if let Some(id) = kv::get::<u64>(&entry_id_key(&key))? {
+        let count: usize = kv::get(KV_ORDER_COUNT)?.unwrap_or_default();
+        let last = count.saturating_sub(1) as u64;
+        if id != last
+            && let Some(moved) = kv::get::<String>(&entry_key(last))?
+        {
+            // Fill the gap with the last entry, so the index stays dense
+            kv::set(&entry_key(id), &moved)?;
+            kv::set(&entry_id_key(&moved), &id)?;
+        }
+        kv::remove(&entry_key(last))?;
+        kv::remove(&entry_id_key(&key))?;
+        kv::set(KV_ORDER_COUNT, &count.saturating_sub(1))?;
+    }
+    kv::remove(&order_key(output))?;
+    Ok(Some(order))
+}
+
+fn entry_key(id: u64) -> String {
+    format!("{KV_ORDER_ENTRY_PREFIX}{id}")
+}
+
+fn entry_id_key(output: &str) -> String {
+    format!("{KV_ORDER_ENTRY_ID_PREFIX}{output}")
+}
+
+/// Store an order which isn't tracked yet, and give it the next index entry.
+fn add(order: &ManagedStrategy) -> WorkerResult<()> {
+    let key = order.output.to_string();
+    let count: usize = kv::get(KV_ORDER_COUNT)?.unwrap_or_default();
+    let id = count as u64;
+    kv::set(&order_key(&key), order)?;
+    kv::set(&entry_key(id), &key)?;
+    kv::set(&entry_id_key(&key), &id)?;
+    kv::set(KV_ORDER_COUNT, &(count + 1))
+}
+
+/// Move orders out of the legacy `managed_orders` vector, if there is one. This only touches the
+/// KV store once per worker instance.
+fn migrate() -> WorkerResult<()> {
+    if MIGRATED.get() {
+        return Ok(());
+    }
+    if let Some(legacy) = kv::get::<Vec<ManagedStrategy>>(KV_LEGACY_MANAGED_ORDERS)? {
+        for order in &legacy {
+            if kv::get::<ManagedStrategy>(&order_key(&order.output))?.is_none() {
+                add(order)?;
+            }
+        }
+        invalidate();
+        kv::remove(KV_LEGACY_MANAGED_ORDERS)?;
+        info!("migrated {} orders to the order index", legacy.len());
+    }
+    MIGRATED.set(true);
+    Ok(())
+}
+
+#[cfg(test)]
+fn tracked() -> Vec<OutputReference> {
+    let mut outputs: Vec<OutputReference> = all().unwrap().into_iter().map(|o| o.output).collect();
+    outputs.sort_by_key(|output| output.to_string());
+    outputs
+}
+
+#[test]
+pub fn test_insert_and_remove_orders() {
+    use crate::{test_strategy, types::test_output};
+
+    for id in 1..=4 {
+        assert!(insert(&test_strategy(id)).unwrap());
+    }
+    assert!(!insert(&test_strategy(2)).unwrap());
+    assert_eq!(count().unwrap(), 4);
+    assert_eq!(
+        get(&test_output(3)).unwrap().unwrap().output,
+        test_output(3)
+    );
+
+    // Removing an order from the middle moves the last entry into its place
+    assert!(remove(&test_output(2)).unwrap().is_some());
+    assert!(remove(&test_output(2)).unwrap().is_none());
+    assert!(get(&test_output(2)).unwrap().is_none());
+    assert_eq!(count().unwrap(), 3);
+    assert_eq!(
+        kv::get::<String>(&entry_key(1)).unwrap(),
+        Some(test_output(4).to_string())
+    );
+    assert_eq!(
+        kv::get::<u64>(&entry_id_key(&test_output(4).to_string())).unwrap(),
+        Some(1)
+    );
+    assert_eq!(
+        tracked(),
+        vec![test_output(1), test_output(3), test_output(4)]
+    );
+
+    // Removing the last entry, and then the rest
+    assert!(remove(&test_output(3)).unwrap().is_some());
+    assert_eq!(tracked(), vec![test_output(1), test_output(4)]);
+    assert!(remove(&test_output(1)).unwrap().is_some());
+    assert!(remove(&test_output(4)).unwrap().is_some());
+    assert_eq!(count().unwrap(), 0);
+    assert!(all().unwrap().is_empty());
+    assert_eq!(kv::keys(), vec![KV_ORDER_COUNT]);
+
+    // An order can be tracked again once it's removed, e.g. after a rollback
+    assert!(insert(&test_strategy(2)).unwrap());
+    assert_eq!(tracked(), vec![test_output(2)]);
+}
+
+#[test]
+pub fn test_migrate_legacy_orders() {
+    use crate::{test_strategy, types::test_output};
+
+    let legacy = vec![test_strategy(1), test_strategy(2)];
+    kv::set(KV_LEGACY_MANAGED_ORDERS, &legacy).unwrap();
+    assert_eq!(count().unwrap(), 2);
+    assert_eq!(tracked(), vec![test_output(1), test_output(2)]);
+    assert!(
+        kv::get::<Vec<ManagedStrategy>>(KV_LEGACY_MANAGED_ORDERS)
+            .unwrap()
+            .is_none()
+    );
+    assert!(!insert(&test_strategy(1)).unwrap());
+
+    // Only the first lookup migrates
+    kv::set(KV_LEGACY_MANAGED_ORDERS, &vec![test_strategy(3)]).unwrap();
+    assert!(get(&test_output(3)).unwrap().is_none());
+    assert_eq!(count().unwrap(), 2);
+}
+
+#[test]
+pub fn test_all_orders_are_cached() {
+    use crate::{test_strategy, types::test_output};
+
+    assert!(insert(&test_strategy(1)).unwrap());
+    assert_eq!(tracked(), vec![test_output(1)]);
+
+    // Once listed, the orders aren't read from the store again...
+    kv::remove(&order_key(&test_output(1))).unwrap();
+    assert_eq!(tracked(), vec![test_output(1)]);
+
+    // ...until an order is added or removed
+    assert!(insert(&test_strategy(2)).unwrap());
+    assert_eq!(tracked(), vec![test_output(2)]);
+    assert!(remove(&test_output(2)).unwrap().is_some());
+    assert!(tracked().is_empty());
+}
@@ -0,0 +1,112 @@
+//! The latest state of every Sundae pool we've seen, available from any callback.
+
+use balius_sdk::WorkerResult;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    PoolState,
+    journal::{self, Change},
+    kv,
+    types::{AssetId, InlineAssetId},
+};
+
+const KV_POOL_INDEX: &str = "pool_index";
+const KV_POOL_PREFIX: &str = "pool:";
+const KV_SNAPSHOT_PREFIX: &str = "pool_snapshot:";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PoolIndexEntry {
+    ident: String,
+    assets: (InlineAssetId, InlineAssetId),
+}
+
+fn pool_key(ident: &[u8]) -> String {
+    format!("{KV_POOL_PREFIX}{}", hex::encode(ident))
+}
+
+/// The latest state of the pool with the given identifier.
+pub fn latest(ident: &[u8]) -> WorkerResult<Option<PoolState>> {
+    kv::get(&pool_key(ident))
+}
+
+/// The latest state of every pool trading the given pair of assets, in either order.
+pub fn for_pair(a: &AssetId, b: &AssetId) -> WorkerResult<Vec<PoolState>> {
+    let index: Vec<PoolIndexEntry> = kv::get(KV_POOL_INDEX)?.unwrap_or_default();
+    let mut pools = vec![];
+    for entry in index {
+        let asset_a = AssetId::from(entry.assets.0);
+        let asset_b = AssetId::from(entry.assets.1);
+        let matches = (&asset_a == a && &asset_b == b) || (&asset_a == b && &asset_b == a);
+        if !matches {
+            continue;
+        }
+        if let Some(pool) = kv::get(&format!("{KV_POOL_PREFIX}{}", entry.ident))? {
+            pools.push(pool);
+        }
+    }
+    Ok(pools)
+}
+
+/// Record a new pool state, journaling the state it replaces so a rollback can put it back.
+/// Returns false, and leaves the store untouched, if we've already seen a newer state.
+pub(crate) fn update(pool: &PoolState) -> WorkerResult<bool> {
+    let ident = pool.pool_datum.identifier.clone();
+    let previous = latest(&ident)?;
+    if previous.as_ref().is_some_and(|p| p.slot > pool.slot) {
+        return Ok(false);
+    }
+    let previous = match &previous {
+        Some(previous) => Some(snapshot(pool.slot, previous)?),
+        None => None,
+    };
+    store(pool)?;
+    journal::record(pool.slot, Change::PoolUpdated { ident, previous })?;
+    Ok(true)
+}
+
+/// Record a new pool state.
+pub(crate) fn store(pool: &PoolState) -> WorkerResult<()> {
+    let ident = hex::encode(&pool.pool_datum.identifier);
+    let mut index: Vec<PoolIndexEntry> = kv::get(KV_POOL_INDEX)?.unwrap_or_default();
+    if !index.iter().any(|entry| entry.ident == ident) {
+        index.push(PoolIndexEntry {
+            ident,
+            assets: pool.pool_datum.assets.clone(),
+        });
+        kv::set(KV_POOL_INDEX, &index)?;
+    }
+    kv::set(&pool_key(&pool.pool_datum.identifier), pool)
+}
+
+/// Keep the state a pool had before it was updated in the given slot, so the update can be
+/// rolled back, returning the key it's kept under. Only the first update in a slot is kept, since
+/// rollbacks undo whole slots.
+fn snapshot(slot: u64, previous: &PoolState) -> WorkerResult<String> {
+    let key = format!(
+        "{KV_SNAPSHOT_PREFIX}{slot}:{}",
+        hex::encode(&previous.pool_datum.identifier)
+    );
+    if kv::get::<PoolState>(&key)?.is_none() {
+        kv::set(&key, previous)?;
+    }
+    Ok(key)
+}
+
+/// Put back the state a pool had before a rollback, from the key [`snapshot`] returned; None if
+/// we hadn't seen the pool before. The snapshot is left in place, for the journal to remove.
+pub(crate) fn restore(ident: &[u8], snapshot: Option<&str>) -> WorkerResult<()> {
+    let previous = match snapshot {
+        Some(key) => kv::get::<PoolState>(key)?,
+        None => None,
+    };
+    match previous {
+        Some(pool) => store(&pool),
+        None => {
+            let encoded = hex::encode(ident);
+            let mut index: Vec<PoolIndexEntry> = kv::get(KV_POOL_INDEX)?.unwrap_or_default();
+            index.retain(|entry| entry.ident != encoded);
+            kv::set(KV_POOL_INDEX, &index)?;
+            kv::remove(&pool_key(ident))
+        }
+    }
+}
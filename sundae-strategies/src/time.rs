@@ -0,0 +1,172 @@
+//! Converting between slots and POSIX time, across the eras of each network's history.
+
+use std::fmt;
+
+use crate::Network;
+
+/// Byron slots lasted 20 seconds.
+const BYRON_SLOT_LENGTH: u64 = 20_000;
+
+/// A stretch of the chain's history during which slots had a fixed length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Era {
+    /// The first slot of the era.
+    pub start_slot: u64,
+    /// When the era started, in POSIX milliseconds.
+    pub start_millis: u64,
+    /// The length of each slot, in milliseconds.
+    pub slot_length: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TimeError {
+    /// The time is before the network's first block.
+    BeforeSystemStart(u64),
+    /// The slot or time is too far in the future to represent.
+    Overflow,
+    /// The network's slots have no length, so times can't be converted to slots.
+    ZeroSlotLength,
+}
+
+impl fmt::Display for TimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BeforeSystemStart(millis) => {
+                write!(f, "{millis} is before the network's system start")
+            }
+            Self::Overflow => f.write_str("slot or time is out of range"),
+            Self::ZeroSlotLength => f.write_str("the network's slot length is zero"),
+        }
+    }
+}
+
+impl std::error::Error for TimeError {}
+
+impl From<TimeError> for balius_sdk::Error {
+    fn from(value: TimeError) -> Self {
+        balius_sdk::Error::Internal(value.to_string())
+    }
+}
+
+/// The eras of a network which started in Byron, given its system start in POSIX seconds and
+/// the first Shelley slot.
+fn byron_then_shelley(system_start: u64, shelley_start_slot: u64, slot_length: u64) -> Vec<Era> {
+    let byron = Era {
+        start_slot: 0,
+        start_millis: system_start * 1000,
+        slot_length: BYRON_SLOT_LENGTH,
+    };
+    if shelley_start_slot == 0 {
+        return vec![Era {
+            slot_length,
+            ..byron
+        }];
+    }
+    let shelley = Era {
+        start_slot: shelley_start_slot,
+        start_millis: byron.start_millis + shelley_start_slot * BYRON_SLOT_LENGTH,
+        slot_length,
+    };
+    vec![byron, shelley]
+}
+
+impl Network {
+    /// Every era of this network with a distinct slot length, oldest first.
+    pub fn eras(&self) -> Vec<Era> {
+        match self {
+            Self::Preview => byron_then_shelley(1666656000, 0, 1000),
+            Self::Preprod => byron_then_shelley(1654041600, 86400, 1000),
+            Self::Mainnet => byron_then_shelley(1506203091, 4492800, 1000),
+            Self::Custom(custom) => byron_then_shelley(
+                custom.system_start,
+                custom.shelley_start_slot,
+                custom.slot_length,
+            ),
+        }
+    }
+
+    /// Get the UNIX time associated with a slot, in milliseconds
+    pub fn to_unix_time(&self, slot: u64) -> Result<u64, TimeError> {
+        let eras = self.eras();
+        let era = eras
+            .iter()
+            .rev()
+            .find(|era| era.start_slot <= slot)
+            .expect("every network has an era starting at slot 0");
+        (slot - era.start_slot)
+            .checked_mul(era.slot_length)
+            .and_then(|offset| era.start_millis.checked_add(offset))
+            .ok_or(TimeError::Overflow)
+    }
+
+    /// Get the slot containing a UNIX time, in milliseconds
+    pub fn unix_time_to_slot(&self, millis: u64) -> Result<u64, TimeError> {
+        let eras = self.eras();
+        let era = eras
+            .iter()
+            .rev()
+            .find(|era| era.start_millis <= millis)
+            .ok_or(TimeError::BeforeSystemStart(millis))?;
+        let offset = (millis - era.start_millis)
+            .checked_div(era.slot_length)
+            .ok_or(TimeError::ZeroSlotLength)?;
+        era.start_slot
+            .checked_add(offset)
+            .ok_or(TimeError::Overflow)
+    }
+}
+
+#[test]
+pub fn test_mainnet_time_conversion() {
+    let mainnet = Network::Mainnet;
+
+    // Byron genesis, 2017-09-23T21:44:51Z
+    assert_eq!(mainnet.to_unix_time(0), Ok(1506203091000));
+    // A Byron slot lasts 20 seconds
+    assert_eq!(mainnet.to_unix_time(1), Ok(1506203111000));
+    assert_eq!(mainnet.unix_time_to_slot(1506203101000), Ok(0));
+    // The Shelley hard fork, 2020-07-29T21:44:51Z
+    assert_eq!(mainnet.to_unix_time(4492800), Ok(1596059091000));
+    // The Alonzo hard fork at the start of epoch 290, 2021-09-12T21:44:51Z
+    assert_eq!(mainnet.to_unix_time(39916800), Ok(1631483091000));
+    assert_eq!(mainnet.unix_time_to_slot(1631483091000), Ok(39916800));
+    assert_eq!(mainnet.unix_time_to_slot(1631483091999), Ok(39916800));
+
+    assert_eq!(
+        mainnet.unix_time_to_slot(1506203090999),
+        Err(TimeError::BeforeSystemStart(1506203090999))
+    );
+    assert_eq!(mainnet.to_unix_time(u64::MAX), Err(TimeError::Overflow));
+}
+
+#[test]
+pub fn test_preview_time_conversion() {
+    let preview = Network::Preview;
+
+    // Preview starts in Shelley at 2022-10-25T00:00:00Z, with one second slots
+    assert_eq!(preview.to_unix_time(0), Ok(1666656000000));
+    assert_eq!(preview.to_unix_time(86400), Ok(1666742400000));
+    assert_eq!(preview.unix_time_to_slot(1666742400500), Ok(86400));
+}
+
+#[test]
+pub fn test_custom_slot_length() {
+    let network: Network =
+        serde_json::from_str(r#"{ "custom": { "system_start": 1666656000, "slot_length": 200 } }"#)
+            .unwrap();
+    assert_eq!(network.to_unix_time(5), Ok(1666656001000));
+    assert_eq!(network.unix_time_to_slot(1666656001100), Ok(5));
+
+    let zero = r#"{ "custom": { "system_start": 1666656000, "slot_length": 0 } }"#;
+    assert!(serde_json::from_str::<Network>(zero).is_err());
+
+    // Networks built in code skip that check, so converting still mustn't divide by zero
+    let Network::Custom(mut custom) = network else {
+        unreachable!()
+    };
+    custom.slot_length = 0;
+    assert_eq!(
+        Network::Custom(custom).unix_time_to_slot(1666656001100),
+        Err(TimeError::ZeroSlotLength)
+    );
+}
@@ -0,0 +1,155 @@
+//! Decimals and tickers for tokens, so amounts and prices can be shown in human units.
+//!
+//! Strategies can include a [`TokenRegistry`] in their worker config, listing the tokens they
+//! trade; anything not listed there falls back to a small built-in table.
+
+use std::iter;
+
+use serde::Deserialize;
+
+use crate::types::AssetId;
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenMetadata {
+    pub ticker: String,
+    pub decimals: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct TokenEntry {
+    asset: AssetId,
+    #[serde(flatten)]
+    metadata: TokenMetadata,
+}
+
+/// Well known tokens, as (hex policy id, hex asset name, ticker, decimals).
+const BUILT_IN: &[(&str, &str, &str, u32)] = &[
+    ("", "", "ADA", 6),
+    // Mainnet
+    (
+        "9a9693a9a37912a5097918f97918d15240c92ab729a0b7c4aa144d77",
+        "53554e444145",
+        "SUNDAE",
+        6,
+    ),
+    (
+        "f66d78b4a3cb3d37afa0ec36461e51ecbde00f26c8f0a68f94b69880",
+        "69555344",
+        "iUSD",
+        6,
+    ),
+    (
+        "29d222ce763455e3d7a09a665ce554f00ac89d2e99a1a83d267170c6",
+        "4d494e",
+        "MIN",
+        6,
+    ),
+    // Preview; the Sundae test tokens have no registered decimals, so they count as indivisible
+    (
+        "99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e15",
+        "534245525259",
+        "SBERRY",
+        0,
+    ),
+];
+
+/// Token metadata from the worker config, on top of the built-in table.
+///
+/// In a JSON config this is a list of `{ "asset": "policyId.assetName", "ticker": ..., "decimals": ... }`.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct TokenRegistry {
+    tokens: Vec<TokenEntry>,
+}
+
+impl TokenRegistry {
+    /// The metadata for a token, preferring the worker config over the built-in table.
+    pub fn metadata(&self, asset: &AssetId) -> Option<TokenMetadata> {
+        if let Some(entry) = self.tokens.iter().find(|entry| &entry.asset == asset) {
+            return Some(entry.metadata.clone());
+        }
+        let policy_id = hex::encode(&asset.policy_id);
+        let asset_name = hex::encode(&asset.asset_name);
+        BUILT_IN
+            .iter()
+            .find(|(policy, name, _, _)| *policy == policy_id && *name == asset_name)
+            .map(|(_, _, ticker, decimals)| TokenMetadata {
+                ticker: ticker.to_string(),
+                decimals: *decimals,
+            })
+    }
+
+    /// The number of decimal places for a token; tokens we know nothing about are assumed to be
+    /// indivisible.
+    pub fn decimals(&self, asset: &AssetId) -> u32 {
+        self.metadata(asset).map(|m| m.decimals).unwrap_or(0)
+    }
+
+    /// A short name for a token, falling back to its hex encoded asset name.
+    pub fn ticker(&self, asset: &AssetId) -> String {
+        self.metadata(asset)
+            .map(|m| m.ticker)
+            .unwrap_or_else(|| hex::encode(&asset.asset_name))
+    }
+
+    /// Convert an on-chain amount into human units, e.g. lovelace to ADA.
+    pub fn to_human(&self, asset: &AssetId, amount: u64) -> f64 {
+        amount as f64 / 10f64.powi(self.decimals(asset) as i32)
+    }
+
+    /// Convert an amount in human units into the on-chain amount, rounding down. The amount is
+    /// shifted by its decimal digits rather than multiplied, so amounts like 1.005 ADA, which
+    /// floats hold as slightly less, still come out exact.
+    pub fn from_human(&self, asset: &AssetId, amount: f64) -> u64 {
+        if amount.is_nan() || amount <= 0.0 {
+            return 0;
+        }
+        // Displaying a float gives the shortest decimal which parses back to it, never in
+        // scientific notation
+        let digits = amount.to_string();
+        let (whole, fraction) = digits.split_once('.').unwrap_or((&digits, ""));
+        let fraction: String = fraction
+            .chars()
+            .chain(iter::repeat('0'))
+            .take(self.decimals(asset) as usize)
+            .collect();
+        format!("{whole}{fraction}").parse().unwrap_or(u64::MAX)
+    }
+}
+
+#[test]
+pub fn test_token_registry() {
+    let ada = AssetId::from((vec![], vec![]));
+    let sberry = AssetId::from((
+        hex::decode("99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e15").unwrap(),
+        b"SBERRY".to_vec(),
+    ));
+    let unknown = AssetId::from((vec![0xcd; 28], b"OTHER".to_vec()));
+
+    let built_in = TokenRegistry::default();
+    assert_eq!(built_in.ticker(&ada), "ADA");
+    assert_eq!(built_in.ticker(&sberry), "SBERRY");
+    assert_eq!(built_in.ticker(&unknown), "4f54484552");
+    assert_eq!(built_in.decimals(&unknown), 0);
+
+    assert_eq!(built_in.to_human(&ada, 1_500_000), 1.5);
+    assert_eq!(built_in.from_human(&ada, 1.5), 1_500_000);
+    // Amounts finer than a token's decimals are rounded down
+    assert_eq!(built_in.from_human(&ada, 0.0000019), 1);
+    // 1.005 is 1.00499999999999989... as a float, which mustn't lose a lovelace
+    assert_eq!(built_in.from_human(&ada, 1.005), 1_005_000);
+    assert_eq!(built_in.from_human(&ada, 0.0), 0);
+    assert_eq!(built_in.from_human(&ada, -1.0), 0);
+    assert_eq!(built_in.from_human(&ada, f64::INFINITY), u64::MAX);
+    assert_eq!(built_in.to_human(&unknown, 42), 42.0);
+
+    // The worker config takes precedence over the built-in table
+    let configured: TokenRegistry = serde_json::from_str(
+        r#"[{ "asset": "99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e15.534245525259", "ticker": "BERRY", "decimals": 6 }]"#,
+    )
+    .unwrap();
+    assert_eq!(configured.ticker(&sberry), "BERRY");
+    assert_eq!(configured.to_human(&sberry, 2_500_000), 2.5);
+    assert_eq!(configured.from_human(&sberry, 2.5), 2_500_000);
+    assert_eq!(configured.ticker(&ada), "ADA");
+}
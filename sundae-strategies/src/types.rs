@@ -1,11 +1,21 @@
-use std::fmt::{self, Debug};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Debug, Display},
+};
 
-use balius_sdk::txbuilder::{codec::minicbor, plutus::BigInt};
+use balius_sdk::txbuilder::{
+    codec::minicbor,
+    plutus::{BigInt, PlutusData},
+};
+use pallas_addresses::{Pointer, ShelleyAddress, ShelleyDelegationPart, ShelleyPaymentPart};
+use pallas_crypto::hash::Hash;
 use plutus_parser::AsPlutus;
 use serde::{Deserialize, Serialize, de};
 use utxorpc_spec::utxorpc::v1alpha::cardano::TxOutput;
 
-#[derive(PartialEq)]
+use crate::{Network, tokens::TokenRegistry};
+
+#[derive(PartialEq, Debug, Clone)]
 pub struct AssetId {
     pub policy_id: Vec<u8>,
     pub asset_name: Vec<u8>,
@@ -68,7 +78,7 @@ pub struct PoolDatum {
     pub protocol_fees: BigInt,
 }
 
-fn to_u64(big_int: &BigInt) -> Option<u64> {
+pub(crate) fn to_u64(big_int: &BigInt) -> Option<u64> {
     match big_int {
         BigInt::Int(int) => u64::try_from(int.0).ok(),
         BigInt::BigUInt(_) | BigInt::BigNInt(_) => None,
@@ -76,30 +86,140 @@ fn to_u64(big_int: &BigInt) -> Option<u64> {
 }
 
 pub fn asset_amount(output: &TxOutput, find_asset: &AssetId) -> u64 {
-    if find_asset.is_ada() {
-        output.coin
-    } else {
-        output
+    Value::from(output).get(find_asset)
+}
+
+/// An amount of ADA and native assets, such as the contents of a UTXO.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Value {
+    pub lovelace: u64,
+    /// Native asset amounts, by policy id then asset name.
+    pub assets: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, u64>>,
+}
+
+impl Value {
+    pub fn from_lovelace(lovelace: u64) -> Self {
+        Self {
+            lovelace,
+            ..Self::default()
+        }
+    }
+
+    /// How much of an asset this value holds.
+    pub fn get(&self, asset: &AssetId) -> u64 {
+        if asset.is_ada() {
+            return self.lovelace;
+        }
+        self.assets
+            .get(&asset.policy_id)
+            .and_then(|assets| assets.get(&asset.asset_name))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Add some amount of an asset to this value.
+    pub fn checked_add_asset(&mut self, asset: &AssetId, amount: u64) -> Option<()> {
+        if asset.is_ada() {
+            self.lovelace = self.lovelace.checked_add(amount)?;
+            return Some(());
+        }
+        if amount == 0 {
+            return Some(());
+        }
+        let held = self
             .assets
+            .entry(asset.policy_id.clone())
+            .or_default()
+            .entry(asset.asset_name.clone())
+            .or_default();
+        *held = held.checked_add(amount)?;
+        Some(())
+    }
+
+    /// The sum of two values, or None if any amount would overflow.
+    pub fn checked_add(&self, other: &Value) -> Option<Value> {
+        let mut sum = self.clone();
+        for (asset, amount) in other.iter() {
+            sum.checked_add_asset(&asset, amount)?;
+        }
+        Some(sum)
+    }
+
+    /// This value with `other` taken away, or None if this value doesn't contain all of `other`.
+    pub fn checked_sub(&self, other: &Value) -> Option<Value> {
+        let mut difference = self.clone();
+        difference.lovelace = difference.lovelace.checked_sub(other.lovelace)?;
+        for (asset, amount) in other.iter().filter(|(asset, _)| !asset.is_ada()) {
+            let policy = difference.assets.get_mut(&asset.policy_id)?;
+            let held = policy.get_mut(&asset.asset_name)?;
+            *held = held.checked_sub(amount)?;
+            if *held == 0 {
+                policy.remove(&asset.asset_name);
+            }
+            if policy.is_empty() {
+                difference.assets.remove(&asset.policy_id);
+            }
+        }
+        Some(difference)
+    }
+
+    /// Whether this value holds at least as much of every asset as `other`.
+    pub fn contains(&self, other: &Value) -> bool {
+        other
             .iter()
-            .filter_map(|multiasset| {
-                let policy_id = multiasset.policy_id.to_vec();
-                multiasset
-                    .assets
-                    .iter()
-                    .filter_map(|asset| {
-                        let asset_name = asset.name.to_vec();
-                        if policy_id == find_asset.policy_id && asset_name == find_asset.asset_name
-                        {
-                            Some(asset.output_coin)
-                        } else {
-                            None
-                        }
-                    })
-                    .next()
+            .all(|(asset, amount)| self.get(&asset) >= amount)
+    }
+
+    /// Every asset in this value with its amount, starting with ADA.
+    pub fn iter(&self) -> impl Iterator<Item = (AssetId, u64)> + '_ {
+        let ada = (AssetId::from((vec![], vec![])), self.lovelace);
+        let assets = self.assets.iter().flat_map(|(policy_id, assets)| {
+            assets.iter().map(|(asset_name, amount)| {
+                (
+                    AssetId::from((policy_id.clone(), asset_name.clone())),
+                    *amount,
+                )
             })
-            .next()
-            .unwrap_or(0u64)
+        });
+        std::iter::once(ada).chain(assets)
+    }
+}
+
+/// Values are ordered by containment, so two values which each hold something the other doesn't
+/// can't be compared.
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        match (self.contains(other), other.contains(self)) {
+            (true, true) => Some(std::cmp::Ordering::Equal),
+            (true, false) => Some(std::cmp::Ordering::Greater),
+            (false, true) => Some(std::cmp::Ordering::Less),
+            (false, false) => None,
+        }
+    }
+}
+
+impl From<SingletonValue> for Value {
+    fn from((policy_id, asset_name, amount): SingletonValue) -> Self {
+        let mut value = Value::default();
+        value
+            .checked_add_asset(&AssetId::from((policy_id, asset_name)), amount)
+            .expect("a single amount can't overflow");
+        value
+    }
+}
+
+impl From<&TxOutput> for Value {
+    fn from(output: &TxOutput) -> Self {
+        let mut value = Value::from_lovelace(output.coin);
+        for multiasset in &output.assets {
+            for asset in &multiasset.assets {
+                let id = AssetId::from((multiasset.policy_id.to_vec(), asset.name.to_vec()));
+                value
+                    .checked_add_asset(&id, asset.output_coin)
+                    .expect("an output holds at most u64::MAX of each asset");
+            }
+        }
+        value
     }
 }
 
@@ -107,21 +227,132 @@ impl PoolDatum {
     /// The raw price of the assets in the pool; not that this doesn't account for decimal places: for example,
     /// for an ADA/SUNDAE pair, this will give the lovelace per sprinkles
     /// If the decimal places on the token are the same, this will work out to the same value, but if they
-    /// have different decimal places, this could be non-intuitive
+    /// have different decimal places, this could be non-intuitive; see [`PoolDatum::price`]
     pub fn raw_price(&self, output: &TxOutput) -> f64 {
+        let (reserves_a, reserves_b) = self
+            .reserves(output)
+            .expect("the pool protocol fees should never exceed the ADA it holds");
+
+        (reserves_a as f64) / (reserves_b as f64)
+    }
+
+    /// The amount of each asset in the pool which is available to trade against, in the same
+    /// order as `assets`. ADA held on behalf of the protocol as fees isn't part of the reserves.
+    pub fn reserves(&self, output: &TxOutput) -> Option<(u64, u64)> {
+        let asset_a: AssetId = self.assets.0.clone().into();
+        let asset_b: AssetId = self.assets.1.clone().into();
+
+        let protocol_fees = Value::from_lovelace(to_u64(&self.protocol_fees)?);
+        let reserves = Value::from(output).checked_sub(&protocol_fees)?;
+        Some((reserves.get(&asset_a), reserves.get(&asset_b)))
+    }
+
+    /// The price of asset B in units of asset A, adjusted for each token's decimal places: for
+    /// example, for an ADA/SUNDAE pair, this will give the ADA per SUNDAE
+    pub fn price(&self, output: &TxOutput, tokens: &TokenRegistry) -> Option<f64> {
+        let (reserves_a, reserves_b) = self.reserves(output)?;
+        if reserves_b == 0 {
+            return None;
+        }
         let asset_a: AssetId = self.assets.0.clone().into();
         let asset_b: AssetId = self.assets.1.clone().into();
+        Some(tokens.to_human(&asset_a, reserves_a) / tokens.to_human(&asset_b, reserves_b))
+    }
 
-        let reserves_a = if asset_a.is_ada() {
-            output.coin
-                - to_u64(&self.protocol_fees)
-                    .expect("the pool protocol fees should never exceed u64 max")
+    /// Whether the pool accepts swaps at the given POSIX time, in milliseconds.
+    pub fn is_market_open(&self, now_millis: u64) -> bool {
+        to_u64(&self.market_open).is_some_and(|open| open <= now_millis)
+    }
+
+    /// Quote a swap of `give` units of `offer` against this pool at the given POSIX time, in
+    /// milliseconds, using the same integer math as the pool script. Returns None if the market
+    /// isn't open yet, the pool doesn't trade `offer`, has no liquidity, or the amounts are too
+    /// large to quote.
+    pub fn quote_swap(
+        &self,
+        output: &TxOutput,
+        offer: &AssetId,
+        give: u64,
+        now_millis: u64,
+    ) -> Option<SwapQuote> {
+        if !self.is_market_open(now_millis) {
+            return None;
+        }
+        let asset_a: AssetId = self.assets.0.clone().into();
+        let asset_b: AssetId = self.assets.1.clone().into();
+        let (reserves_a, reserves_b) = self.reserves(output)?;
+
+        // Selling asset A for asset B is a bid, the other direction an ask
+        let (reserve_give, reserve_take, fee) = if offer == &asset_a {
+            (reserves_a, reserves_b, &self.bid_fees_per_10_thousand)
+        } else if offer == &asset_b {
+            (reserves_b, reserves_a, &self.ask_fees_per_10_thousand)
         } else {
-            asset_amount(output, &asset_a)
+            return None;
         };
-        let reserves_b = asset_amount(output, &asset_b);
+        SwapQuote::new(reserve_give, reserve_take, give, to_u64(fee)?)
+    }
+}
 
-        (reserves_a as f64) / (reserves_b as f64)
+/// The result of swapping against a constant-product pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapQuote {
+    /// How much of the offered asset goes into the pool.
+    pub give: u64,
+    /// How much of the other asset comes out of the pool.
+    pub takes: u64,
+    /// How much of the offered asset is kept by the pool as a fee.
+    pub fee: u64,
+    /// How far the effective price is from the pool's spot price, fee included, in basis points.
+    pub price_impact_per_10_thousand: u64,
+}
+
+impl SwapQuote {
+    /// Quote a swap of `give` into a pool holding `reserve_give` of the offered asset and
+    /// `reserve_take` of the asset received, charging `fee_per_10_thousand` basis points.
+    pub fn new(
+        reserve_give: u64,
+        reserve_take: u64,
+        give: u64,
+        fee_per_10_thousand: u64,
+    ) -> Option<Self> {
+        if reserve_give == 0 || reserve_take == 0 || give == 0 || fee_per_10_thousand > 10_000 {
+            return None;
+        }
+        let (reserve_give, reserve_take, give_wide) =
+            (reserve_give as u128, reserve_take as u128, give as u128);
+        let difference = 10_000 - fee_per_10_thousand as u128;
+
+        let numerator = reserve_take
+            .checked_mul(give_wide)?
+            .checked_mul(difference)?;
+        let denominator = reserve_give
+            .checked_mul(10_000)?
+            .checked_add(give_wide.checked_mul(difference)?)?;
+        let takes = numerator / denominator;
+
+        // The fee is the portion of give which doesn't count towards the swap, rounded up as the
+        // pool rounds the amount it pays out down
+        let fee = (give_wide * fee_per_10_thousand as u128).div_ceil(10_000);
+
+        // takes / give as a fraction of the spot price reserve_take / reserve_give
+        let relative = takes.checked_mul(reserve_give)?.checked_mul(10_000)?
+            / give_wide.checked_mul(reserve_take)?;
+        let price_impact_per_10_thousand = 10_000u128.saturating_sub(relative);
+
+        Some(Self {
+            give,
+            takes: u64::try_from(takes).ok()?,
+            fee: u64::try_from(fee).ok()?,
+            price_impact_per_10_thousand: price_impact_per_10_thousand as u64,
+        })
+    }
+
+    /// The least we should accept from this swap, allowing the price to move against us by
+    /// `slippage_per_10_thousand` basis points before the order is executed.
+    pub fn min_received(&self, slippage_per_10_thousand: u64) -> u64 {
+        let tolerance = 10_000 - slippage_per_10_thousand.min(10_000) as u128;
+        (self.takes as u128 * tolerance / 10_000) as u64
     }
 }
 
@@ -158,15 +389,153 @@ pub struct SubmitSSE {
 
 #[derive(AsPlutus, Serialize, Deserialize, Debug, Clone)]
 pub enum MultisigScript {
-    Signature { key_hash: Vec<u8> },
+    Signature {
+        key_hash: Vec<u8>,
+    },
+    AllOf {
+        scripts: Vec<MultisigScript>,
+    },
+    AnyOf {
+        scripts: Vec<MultisigScript>,
+    },
+    AtLeast {
+        required: u64,
+        scripts: Vec<MultisigScript>,
+    },
+    /// Satisfied by transactions whose validity range ends before this POSIX time, in milliseconds.
+    Before {
+        time: u64,
+    },
+    /// Satisfied by transactions whose validity range starts after this POSIX time, in milliseconds.
+    After {
+        time: u64,
+    },
+    /// Satisfied by transactions which withdraw from this stake script.
+    Script {
+        script_hash: Vec<u8>,
+    },
 }
 
 #[derive(AsPlutus, Serialize, Deserialize, Debug, Clone)]
 pub enum Destination {
-    #[variant = 1]
+    /// Pay the order's output to an address, with a datum.
+    Fixed { address: Address, datum: Datum },
+    /// Pay the order's output back to the order script, so it can be executed again.
     Self_,
 }
 
+#[derive(AsPlutus, Serialize, Deserialize, Debug, Clone)]
+pub enum Datum {
+    NoDatum,
+    DatumHash(Vec<u8>),
+    InlineDatum(PlutusData),
+}
+
+#[derive(AsPlutus, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Credential {
+    VerificationKey(Vec<u8>),
+    Script(Vec<u8>),
+}
+
+#[derive(AsPlutus, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum StakeCredential {
+    Inline(Credential),
+    Pointer {
+        slot_number: u64,
+        transaction_index: u64,
+        certificate_index: u64,
+    },
+}
+
+/// A Cardano address, as seen by Plutus scripts.
+#[derive(AsPlutus, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub payment_credential: Credential,
+    pub stake_credential: Option<StakeCredential>,
+}
+
+#[derive(Debug)]
+pub enum AddressError {
+    Decode(pallas_addresses::Error),
+    /// Byron and stake addresses can't be represented in Plutus.
+    Unsupported,
+    InvalidHash(usize),
+}
+
+fn to_hash(bytes: &[u8]) -> Result<Hash<28>, AddressError> {
+    let hash: [u8; 28] = bytes
+        .try_into()
+        .map_err(|_| AddressError::InvalidHash(bytes.len()))?;
+    Ok(Hash::new(hash))
+}
+
+impl Address {
+    /// Decode a bech32 address, such as `addr1...` or `addr_test1...`.
+    pub fn from_bech32(address: &str) -> Result<Self, AddressError> {
+        let pallas_addresses::Address::Shelley(address) =
+            pallas_addresses::Address::from_bech32(address).map_err(AddressError::Decode)?
+        else {
+            return Err(AddressError::Unsupported);
+        };
+
+        let payment_credential = match address.payment() {
+            ShelleyPaymentPart::Key(hash) => Credential::VerificationKey(hash.to_vec()),
+            ShelleyPaymentPart::Script(hash) => Credential::Script(hash.to_vec()),
+        };
+        let stake_credential = match address.delegation() {
+            ShelleyDelegationPart::Key(hash) => Some(StakeCredential::Inline(
+                Credential::VerificationKey(hash.to_vec()),
+            )),
+            ShelleyDelegationPart::Script(hash) => {
+                Some(StakeCredential::Inline(Credential::Script(hash.to_vec())))
+            }
+            ShelleyDelegationPart::Pointer(pointer) => Some(StakeCredential::Pointer {
+                slot_number: pointer.slot(),
+                transaction_index: pointer.tx_idx(),
+                certificate_index: pointer.cert_idx(),
+            }),
+            ShelleyDelegationPart::Null => None,
+        };
+        Ok(Self {
+            payment_credential,
+            stake_credential,
+        })
+    }
+
+    /// Encode this address as bech32, for the given network.
+    pub fn to_bech32(&self, network: &Network) -> Result<String, AddressError> {
+        let payment = match &self.payment_credential {
+            Credential::VerificationKey(hash) => ShelleyPaymentPart::Key(to_hash(hash)?),
+            Credential::Script(hash) => ShelleyPaymentPart::Script(to_hash(hash)?),
+        };
+        let delegation = match &self.stake_credential {
+            Some(StakeCredential::Inline(Credential::VerificationKey(hash))) => {
+                ShelleyDelegationPart::Key(to_hash(hash)?)
+            }
+            Some(StakeCredential::Inline(Credential::Script(hash))) => {
+                ShelleyDelegationPart::Script(to_hash(hash)?)
+            }
+            Some(StakeCredential::Pointer {
+                slot_number,
+                transaction_index,
+                certificate_index,
+            }) => ShelleyDelegationPart::Pointer(Pointer::new(
+                *slot_number,
+                *transaction_index,
+                *certificate_index,
+            )),
+            None => ShelleyDelegationPart::Null,
+        };
+        let network = match network {
+            Network::Mainnet => pallas_addresses::Network::Mainnet,
+            _ => pallas_addresses::Network::Testnet,
+        };
+        ShelleyAddress::new(network, payment, delegation)
+            .to_bech32()
+            .map_err(AddressError::Decode)
+    }
+}
+
 #[derive(AsPlutus, Clone, Serialize, Deserialize, Debug)]
 pub enum Order {
     Strategy {
@@ -176,19 +545,45 @@ pub enum Order {
         offer: SingletonValue,
         min_received: SingletonValue,
     },
+    /// Add liquidity to the pool, receiving LP tokens.
+    Deposit {
+        assets: (SingletonValue, SingletonValue),
+    },
+    /// Return LP tokens to the pool, receiving a share of its reserves.
+    Withdrawal {
+        amount: SingletonValue,
+    },
+    /// Give assets to the pool's liquidity providers, without receiving anything back.
+    Donation {
+        assets: (SingletonValue, SingletonValue),
+    },
+    /// Record the pool's state onto an oracle token minted under `policy`.
+    Record {
+        policy: InlineAssetId,
+    },
 }
 
 pub type SingletonValue = (Vec<u8>, Vec<u8>, u64);
 
+/// The redeemer used to spend an order from the Sundae v3 order script.
+#[derive(AsPlutus, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum OrderRedeemer {
+    Scoop,
+    Cancel,
+}
+
 #[derive(AsPlutus, Clone, Serialize, Deserialize, Debug)]
 pub enum StrategyAuthorization {
+    /// Executions must be signed by this ed25519 public key.
     Signature { signer: Vec<u8> },
+    /// Executions are authorized by a withdrawal from this stake script in the scoop transaction.
+    Script { script: Vec<u8> },
 }
 
-#[derive(AsPlutus, Clone, Serialize, Deserialize, Debug)]
+#[derive(AsPlutus, Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
 pub struct TransactionId(pub Vec<u8>);
 
-#[derive(AsPlutus, Clone, Serialize, Deserialize)]
+#[derive(AsPlutus, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct OutputReference {
     pub transaction_id: TransactionId,
     pub output_index: u64,
@@ -207,6 +602,12 @@ impl Debug for OutputReference {
     }
 }
 
+impl Display for OutputReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
 #[derive(AsPlutus, Clone)]
 pub struct Interval {
     pub lower_bound: IntervalBound,
@@ -226,6 +627,11 @@ impl Interval {
             },
         }
     }
+
+    /// The finite lower and upper bounds of this interval, in POSIX milliseconds.
+    pub fn bounds(&self) -> (Option<u64>, Option<u64>) {
+        (self.lower_bound.finite(), self.upper_bound.finite())
+    }
 }
 
 #[derive(AsPlutus, Clone)]
@@ -234,6 +640,15 @@ pub struct IntervalBound {
     pub is_inclusive: bool,
 }
 
+impl IntervalBound {
+    fn finite(&self) -> Option<u64> {
+        match self.bound_type {
+            IntervalBoundType::Finite(millis) => Some(millis),
+            IntervalBoundType::NegativeInfinity | IntervalBoundType::PositiveInfinity => None,
+        }
+    }
+}
+
 #[derive(AsPlutus, Clone)]
 pub enum IntervalBoundType {
     NegativeInfinity,
@@ -304,3 +719,423 @@ pub fn test_strategy_serialization() {
     let expected_bytes = hex::decode("d8799fd8799fd8799f5820da432ef16b7aa9b3972bdd42f86e6605c14444e75678f4e6fd75baa01168086fff00ffd8799fd8799fd87a9f1b00000197fb75e4e8ffd87a80ffd8799fd87a9f1b00000197fb9a83e8ffd87a80ffffd87a9f9f40401a00989680ff9f581c99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e154653424552525901ffff40ff").unwrap();
     assert_eq!(bytes, expected_bytes);
 }
+
+#[test]
+pub fn test_swap_quote() {
+    // 1,000 ADA / 1,000 tokens with 5% fees, swapping 10 ADA. These are worked out by hand from
+    // the v3 scoop formula, takes = reserve_b * give * (10000 - fee) / (reserve_a * 10000 + give *
+    // (10000 - fee)), rather than taken from scoops on chain
+    let quote = SwapQuote::new(1_000_000_000, 1_000_000_000, 10_000_000, 500).unwrap();
+    assert_eq!(quote.takes, 9_410_599);
+    assert_eq!(quote.fee, 500_000);
+    assert_eq!(quote.price_impact_per_10_thousand, 590);
+
+    // A 0.3% pool on a lopsided pair, where the order moves the price noticeably
+    let quote = SwapQuote::new(20_000_000_000, 5_000_000, 1_000_000_000, 30).unwrap();
+    assert_eq!(quote.takes, 237_414);
+    assert_eq!(quote.fee, 3_000_000);
+    assert_eq!(quote.price_impact_per_10_thousand, 504);
+    assert_eq!(quote.min_received(100), 235_039);
+
+    assert_eq!(SwapQuote::new(0, 1_000, 10, 30), None);
+    assert_eq!(SwapQuote::new(1_000, 1_000, 10, 10_001), None);
+}
+
+/// An ADA/SBERRY pool holding 1,000 ADA and 2,000 SBERRY after 5 ADA of protocol fees, with a
+/// 0.3% bid fee and a 1% ask fee.
+#[cfg(test)]
+pub(crate) fn test_pool() -> (PoolDatum, TxOutput) {
+    use utxorpc_spec::utxorpc::v1alpha::cardano::{Asset, Multiasset};
+
+    let datum = parse(&hex::decode("d8799f581cabababababababababababababababababababababababababababab9f9f4040ff9f581c99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e1546534245525259ffff1a3b9aca00181e1864d87a80001a004c4b40ff").unwrap()).unwrap();
+    let output = TxOutput {
+        coin: 1_005_000_000,
+        assets: vec![Multiasset {
+            policy_id: hex::decode("99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e15")
+                .unwrap()
+                .into(),
+            assets: vec![Asset {
+                name: b"SBERRY".to_vec().into(),
+                output_coin: 2_000_000_000,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    (datum, output)
+}
+
+/// A reference to the first output of a made-up transaction.
+#[cfg(test)]
+pub(crate) fn test_output(id: u8) -> OutputReference {
+    OutputReference {
+        transaction_id: TransactionId(vec![id; 32]),
+        output_index: 0,
+    }
+}
+
+#[test]
+pub fn test_pool_swap_quote() {
+    let (pool, output) = test_pool();
+    let ada = AssetId::from((vec![], vec![]));
+    let sberry: AssetId = pool.assets.1.clone().into();
+    assert_eq!(pool.reserves(&output), Some((1_000_000_000, 2_000_000_000)));
+
+    // Offering asset A pays the bid fee
+    let quote = pool.quote_swap(&output, &ada, 10_000_000, 0).unwrap();
+    assert_eq!(quote.takes, 19_743_160);
+    assert_eq!(quote.fee, 30_000);
+    assert_eq!(quote.price_impact_per_10_thousand, 129);
+
+    // Offering asset B pays the ask fee
+    let quote = pool.quote_swap(&output, &sberry, 20_000_000, 0).unwrap();
+    assert_eq!(quote.takes, 9_802_950);
+    assert_eq!(quote.fee, 200_000);
+    assert_eq!(quote.price_impact_per_10_thousand, 198);
+
+    let other = AssetId::from((vec![0xcd; 28], b"OTHER".to_vec()));
+    assert_eq!(pool.quote_swap(&output, &other, 10_000_000, 0), None);
+
+    // Nothing can be swapped before the market opens
+    let pool = PoolDatum {
+        market_open: parse(&hex::decode("1b00000197fb75e4e8").unwrap()).unwrap(),
+        ..pool
+    };
+    assert!(!pool.is_market_open(1752270496999));
+    assert_eq!(
+        pool.quote_swap(&output, &ada, 10_000_000, 1752270496999),
+        None
+    );
+    assert!(pool.is_market_open(1752270497000));
+    let quote = pool
+        .quote_swap(&output, &ada, 10_000_000, 1752270497000)
+        .unwrap();
+    assert_eq!(quote.takes, 19_743_160);
+}
+
+#[test]
+pub fn test_pool_price() {
+    let (pool, output) = test_pool();
+    assert_eq!(pool.raw_price(&output), 0.5);
+    // SBERRY is indivisible unless configured otherwise, so each whole unit costs 0.0000005 ADA
+    assert_eq!(
+        pool.price(&output, &TokenRegistry::default()),
+        Some(0.0000005)
+    );
+
+    let tokens: TokenRegistry = serde_json::from_str(
+        r#"[{ "asset": "99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e15.534245525259", "ticker": "SBERRY", "decimals": 6 }]"#,
+    )
+    .unwrap();
+    assert_eq!(pool.price(&output, &tokens), Some(0.5));
+}
+
+/// Check that a value parses back from its Plutus encoding, and optionally that the encoding is
+/// the given hex.
+#[cfg(test)]
+fn assert_round_trips<T: AsPlutus>(value: T, expected: Option<&str>) {
+    let bytes = serialize(value);
+    if let Some(expected) = expected {
+        assert_eq!(hex::encode(&bytes), expected);
+    }
+    let parsed: T = parse(&bytes).unwrap();
+    assert_eq!(serialize(parsed), bytes);
+}
+
+#[test]
+pub fn test_order_serialization() {
+    let ada: SingletonValue = (vec![], vec![], 10000000);
+    let sberry: SingletonValue = (
+        hex::decode("99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e15").unwrap(),
+        hex::decode("534245525259").unwrap(),
+        500,
+    );
+
+    assert_round_trips(
+        Order::Strategy {
+            auth: StrategyAuthorization::Signature {
+                signer: vec![0xab; 32],
+            },
+        },
+        None,
+    );
+    assert_round_trips(
+        Order::Swap {
+            offer: ada.clone(),
+            min_received: sberry.clone(),
+        },
+        None,
+    );
+    assert_round_trips(
+        Order::Deposit {
+            assets: (ada.clone(), sberry.clone()),
+        },
+        Some(
+            "d87b9f9f9f40401a00989680ff9f581c99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e15465342455252591901f4ffffff",
+        ),
+    );
+    assert_round_trips(
+        Order::Withdrawal {
+            amount: sberry.clone(),
+        },
+        Some(
+            "d87c9f9f581c99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e15465342455252591901f4ffff",
+        ),
+    );
+    assert_round_trips(
+        Order::Donation {
+            assets: (ada, sberry.clone()),
+        },
+        None,
+    );
+    assert_round_trips(
+        Order::Record {
+            policy: (sberry.0, sberry.1),
+        },
+        None,
+    );
+}
+
+#[test]
+pub fn test_address_bech32() {
+    // CIP-19 test vectors
+    let key = hex::decode("9493315cd92eb5d8c4304e67b7e16ae36d61d34502694657811a2c8e").unwrap();
+    let script = hex::decode("c37b1b5dc0669f1d3c61a6fddb2e8fde96be87b881c60bce8e8d542f").unwrap();
+    let stake = hex::decode("337b62cfff6403a06a3acbc34f8c46003c69fe79a3628cefa9c47251").unwrap();
+    let cases = [
+        (
+            "addr1qx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgse35a3x",
+            Network::Mainnet,
+            Credential::VerificationKey(key.clone()),
+            Some(StakeCredential::Inline(Credential::VerificationKey(
+                stake.clone(),
+            ))),
+        ),
+        (
+            "addr_test1qz2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgs68faae",
+            Network::Preview,
+            Credential::VerificationKey(key.clone()),
+            Some(StakeCredential::Inline(Credential::VerificationKey(
+                stake.clone(),
+            ))),
+        ),
+        (
+            "addr1z8phkx6acpnf78fuvxn0mkew3l0fd058hzquvz7w36x4gten0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgs9yc0hh",
+            Network::Mainnet,
+            Credential::Script(script.clone()),
+            Some(StakeCredential::Inline(Credential::VerificationKey(
+                stake.clone(),
+            ))),
+        ),
+        (
+            "addr_test1xrphkx6acpnf78fuvxn0mkew3l0fd058hzquvz7w36x4gten0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgscv7swf",
+            Network::Preprod,
+            Credential::Script(script.clone()),
+            Some(StakeCredential::Inline(Credential::Script(stake))),
+        ),
+        (
+            "addr1gx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer5pnz75xxcrzqf96k",
+            Network::Mainnet,
+            Credential::VerificationKey(key.clone()),
+            Some(StakeCredential::Pointer {
+                slot_number: 2498243,
+                transaction_index: 27,
+                certificate_index: 3,
+            }),
+        ),
+        (
+            "addr_test1gz2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer5pnz75xxcrdw5vky",
+            Network::Preview,
+            Credential::VerificationKey(key.clone()),
+            Some(StakeCredential::Pointer {
+                slot_number: 2498243,
+                transaction_index: 27,
+                certificate_index: 3,
+            }),
+        ),
+        (
+            "addr1vx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzers66hrl8",
+            Network::Mainnet,
+            Credential::VerificationKey(key.clone()),
+            None,
+        ),
+        (
+            "addr_test1vz2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzerspjrlsz",
+            Network::Preview,
+            Credential::VerificationKey(key),
+            None,
+        ),
+        (
+            "addr1w8phkx6acpnf78fuvxn0mkew3l0fd058hzquvz7w36x4gtcyjy7wx",
+            Network::Mainnet,
+            Credential::Script(script),
+            None,
+        ),
+    ];
+    for (bech32, network, payment_credential, stake_credential) in cases {
+        let address = Address::from_bech32(bech32).unwrap();
+        assert_eq!(
+            address,
+            Address {
+                payment_credential,
+                stake_credential,
+            }
+        );
+        assert_eq!(address.to_bech32(&network).unwrap(), bech32);
+    }
+
+    assert!(matches!(
+        Address::from_bech32("stake1uyehkck0lajq8gr28t9uxnuvgcqrc6070x3k9r8048z8y5gh6ffgw"),
+        Err(AddressError::Unsupported)
+    ));
+}
+
+#[test]
+pub fn test_destination_serialization() {
+    let inline_datum = Datum::InlineDatum(minicbor::decode(&[0x18, 0x2a]).unwrap());
+    assert_round_trips(
+        Destination::Fixed {
+            address: Address::from_bech32("addr1qx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgse35a3x").unwrap(),
+            datum: inline_datum,
+        },
+        Some("d8799fd8799fd8799f581c9493315cd92eb5d8c4304e67b7e16ae36d61d34502694657811a2c8effd8799fd8799fd8799f581c337b62cfff6403a06a3acbc34f8c46003c69fe79a3628cefa9c47251ffffffffd87b9f182affff"),
+    );
+    assert_round_trips(
+        Destination::Fixed {
+            address: Address::from_bech32(
+                "addr1gx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer5pnz75xxcrzqf96k",
+            )
+            .unwrap(),
+            datum: Datum::DatumHash(vec![0xab; 32]),
+        },
+        Some(
+            "d8799fd8799fd8799f581c9493315cd92eb5d8c4304e67b7e16ae36d61d34502694657811a2c8effd8799fd87a9f1a00261ec3181b03ffffffd87a9f5820ababababababababababababababababababababababababababababababababffff",
+        ),
+    );
+    assert_round_trips(
+        Destination::Fixed {
+            address: Address::from_bech32(
+                "addr1w8phkx6acpnf78fuvxn0mkew3l0fd058hzquvz7w36x4gtcyjy7wx",
+            )
+            .unwrap(),
+            datum: Datum::NoDatum,
+        },
+        Some(
+            "d8799fd8799fd87a9f581cc37b1b5dc0669f1d3c61a6fddb2e8fde96be87b881c60bce8e8d542fffd87a80ffd87980ff",
+        ),
+    );
+    assert_round_trips(Destination::Self_, Some("d87a80"));
+}
+
+#[test]
+pub fn test_multisig_serialization() {
+    let key_hash = hex::decode("9493315cd92eb5d8c4304e67b7e16ae36d61d34502694657811a2c8e").unwrap();
+    let script_hash =
+        hex::decode("337b62cfff6403a06a3acbc34f8c46003c69fe79a3628cefa9c47251").unwrap();
+    let signature = MultisigScript::Signature { key_hash };
+    let script = MultisigScript::Script { script_hash };
+    // 2023-11-14T22:13:20Z
+    let time = 1_700_000_000_000;
+
+    assert_round_trips(
+        signature.clone(),
+        Some("d8799f581c9493315cd92eb5d8c4304e67b7e16ae36d61d34502694657811a2c8eff"),
+    );
+    assert_round_trips(
+        MultisigScript::AllOf {
+            scripts: vec![signature.clone(), script.clone()],
+        },
+        Some(
+            "d87a9f9fd8799f581c9493315cd92eb5d8c4304e67b7e16ae36d61d34502694657811a2c8effd87f9f581c337b62cfff6403a06a3acbc34f8c46003c69fe79a3628cefa9c47251ffffff",
+        ),
+    );
+    assert_round_trips(
+        MultisigScript::AnyOf {
+            scripts: vec![signature.clone(), MultisigScript::Before { time }],
+        },
+        Some(
+            "d87b9f9fd8799f581c9493315cd92eb5d8c4304e67b7e16ae36d61d34502694657811a2c8effd87d9f1b0000018bcfe56800ffffff",
+        ),
+    );
+    assert_round_trips(
+        MultisigScript::AtLeast {
+            required: 2,
+            scripts: vec![signature, MultisigScript::After { time }, script.clone()],
+        },
+        Some(
+            "d87c9f029fd8799f581c9493315cd92eb5d8c4304e67b7e16ae36d61d34502694657811a2c8effd87e9f1b0000018bcfe56800ffd87f9f581c337b62cfff6403a06a3acbc34f8c46003c69fe79a3628cefa9c47251ffffff",
+        ),
+    );
+    assert_round_trips(
+        MultisigScript::Before { time },
+        Some("d87d9f1b0000018bcfe56800ff"),
+    );
+    assert_round_trips(
+        MultisigScript::After { time },
+        Some("d87e9f1b0000018bcfe56800ff"),
+    );
+    assert_round_trips(
+        script,
+        Some("d87f9f581c337b62cfff6403a06a3acbc34f8c46003c69fe79a3628cefa9c47251ff"),
+    );
+}
+
+#[test]
+pub fn test_value_arithmetic() {
+    let ada = AssetId::from((vec![], vec![]));
+    let sberry = AssetId::from((vec![0x99; 28], b"SBERRY".to_vec()));
+    let sundae = AssetId::from((vec![0x9a; 28], b"SUNDAE".to_vec()));
+    let value = |lovelace, berries, sundaes| {
+        let mut value = Value::from_lovelace(lovelace);
+        value.checked_add_asset(&sberry, berries).unwrap();
+        value.checked_add_asset(&sundae, sundaes).unwrap();
+        value
+    };
+
+    let sum = value(2_000_000, 10, 0)
+        .checked_add(&value(3_000_000, 5, 7))
+        .unwrap();
+    assert_eq!(sum, value(5_000_000, 15, 7));
+    assert_eq!(
+        Value::from_lovelace(u64::MAX).checked_add(&Value::from_lovelace(1)),
+        None
+    );
+    assert_eq!(value(0, u64::MAX, 0).checked_add(&value(0, 1, 0)), None);
+
+    assert_eq!(
+        sum.checked_sub(&value(1_000_000, 15, 2)),
+        Some(value(4_000_000, 0, 5))
+    );
+    // Assets which run out are dropped, rather than kept with a zero amount
+    assert!(
+        sum.checked_sub(&value(0, 15, 7))
+            .is_some_and(|rest| rest.assets.is_empty())
+    );
+    assert_eq!(sum.checked_sub(&value(6_000_000, 0, 0)), None);
+    assert_eq!(sum.checked_sub(&value(0, 16, 0)), None);
+    assert_eq!(value(1, 0, 0).checked_sub(&value(0, 1, 0)), None);
+
+    assert_eq!(
+        value(2_000_000, 10, 0).partial_cmp(&value(2_000_000, 10, 0)),
+        Some(std::cmp::Ordering::Equal)
+    );
+    assert!(value(2_000_000, 10, 1) > value(2_000_000, 10, 0));
+    assert!(value(1_000_000, 10, 0) < value(2_000_000, 10, 0));
+    // Each holds something the other doesn't
+    assert_eq!(
+        value(2_000_000, 10, 0).partial_cmp(&value(1_000_000, 20, 0)),
+        None
+    );
+    assert_eq!(value(0, 1, 0).partial_cmp(&value(0, 0, 1)), None);
+
+    assert_eq!(
+        value(2_000_000, 10, 7).iter().collect::<Vec<_>>(),
+        vec![
+            (ada.clone(), 2_000_000),
+            (sberry.clone(), 10),
+            (sundae.clone(), 7)
+        ]
+    );
+    assert_eq!(Value::default().iter().collect::<Vec<_>>(), vec![(ada, 0)]);
+}
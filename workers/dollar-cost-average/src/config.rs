@@ -1,6 +1,6 @@
 use serde::Deserialize;
 
-use sundae_strategies::{Network, types::AssetId};
+use sundae_strategies::{Network, executions::Submitter, types::AssetId};
 
 #[derive(Deserialize)]
 pub struct DCAConfig {
@@ -10,4 +10,6 @@ pub struct DCAConfig {
     pub offer_amount: u64,
     pub receive_token: AssetId,
     pub receive_amount_min: u64,
+    #[serde(default)]
+    pub submitter: Submitter,
 }
@@ -3,7 +3,7 @@ mod config;
 use std::time::Duration;
 
 use balius_sdk::{Ack, Config, Tx, WorkerResult};
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::config::DCAConfig;
 
@@ -21,7 +21,7 @@ fn on_each_tx(
         let slots_elapsed = tx.block_slot - seen.slot;
         if slots_elapsed > config.interval {
             info!("{} slots elapsed, triggering a buy order", slots_elapsed);
-            trigger_buy(config, config.network.to_unix_time(tx.block_slot), seen)?;
+            trigger_buy(config, config.network.to_unix_time(tx.block_slot)?, seen)?;
         } else {
             info!(
                 "{} slots elapsed, out of {}; {} slots remaining before we trigger a buy...",
@@ -54,7 +54,19 @@ fn trigger_buy(config: &config::DCAConfig, now: u64, order: &ManagedStrategy) ->
         ),
     };
 
-    sundae_strategies::submit_execution(&config.network, &order.output, validity_range, swap)?;
+    let outcome = sundae_strategies::submit_execution_with(
+        &config.submitter,
+        &config.network,
+        &order.output,
+        validity_range,
+        swap,
+    )?;
+    if !outcome.is_accepted() {
+        warn!(
+            "execution of {} was not accepted: {:?}",
+            order.output, outcome
+        );
+    }
 
     Ok(())
 }
@@ -1,5 +1,5 @@
 use serde::Deserialize;
-use sundae_strategies::{Network, types::AssetId};
+use sundae_strategies::{Network, executions::Submitter, tokens::TokenRegistry, types::AssetId};
 
 #[derive(Deserialize)]
 pub struct Config {
@@ -7,4 +7,8 @@ pub struct Config {
     pub give_token: AssetId,
     pub receive_token: AssetId,
     pub trail_percent: f64,
+    #[serde(default)]
+    pub tokens: TokenRegistry,
+    #[serde(default)]
+    pub submitter: Submitter,
 }
@@ -2,19 +2,29 @@ mod config;
 
 use std::time::Duration;
 
-use balius_sdk::{Ack, Config, WorkerResult};
+use balius_sdk::{Ack, Config, Tx, WorkerResult};
 use config::Config as StrategyConfig;
 use sundae_strategies::{
-    ManagedStrategy, PoolState, Strategy, kv,
-    types::{Interval, Order, asset_amount},
+    ManagedStrategy, PoolState, SpendReason, Strategy, kv, orders,
+    types::{AssetId, Interval, Order},
 };
-use tracing::info;
+use tracing::{info, warn};
 
 pub const BASE_PRICE_PREFIX: &str = "base_price:";
 fn base_price_key(pool_ident: &String) -> String {
     format!("{BASE_PRICE_PREFIX}{pool_ident}")
 }
 
+/// We only care about pools which trade the pair we're configured for.
+fn is_traded_pool(config: &Config<StrategyConfig>, pool_state: &PoolState) -> bool {
+    let (asset_a, asset_b) = &pool_state.pool_datum.assets;
+    let assets = [
+        AssetId::from(asset_a.clone()),
+        AssetId::from(asset_b.clone()),
+    ];
+    assets.contains(&config.give_token) && assets.contains(&config.receive_token)
+}
+
 fn on_new_pool_state(
     config: &Config<StrategyConfig>,
     pool_state: &PoolState,
@@ -26,8 +36,12 @@ fn on_new_pool_state(
     let base_price = kv::get::<f64>(base_price_key(&pool_ident).as_str())?.unwrap_or(0.0);
 
     info!(
-        "pool update found, with price {} against base price {}",
-        pool_price, base_price
+        "pool update found, with price {} ({:?} in human units) against base price {}",
+        pool_price,
+        pool_state
+            .pool_datum
+            .price(&pool_state.utxo, &config.tokens),
+        base_price
     );
 
     if pool_price < base_price {
@@ -38,7 +52,7 @@ fn on_new_pool_state(
         for strategy in strategies {
             trigger_sell(
                 config,
-                config.network.to_unix_time(pool_state.slot),
+                config.network.to_unix_time(pool_state.slot)?,
                 strategy,
             )?;
         }
@@ -53,6 +67,27 @@ fn on_new_pool_state(
     Ok(Ack)
 }
 
+fn on_strategy_spent(
+    _: &Config<StrategyConfig>,
+    strategy: &ManagedStrategy,
+    _: &Tx,
+    reason: SpendReason,
+) -> WorkerResult<Ack> {
+    info!("order {} was spent: {:?}", strategy.output, reason);
+
+    // Once nothing is trailing this pool any more, forget its base price so the next order starts fresh
+    let Some(pool_ident) = &strategy.order.pool_ident else {
+        return Ok(Ack);
+    };
+    let still_trailing = orders::all()?
+        .iter()
+        .any(|order| order.order.pool_ident.as_ref() == Some(pool_ident));
+    if !still_trailing {
+        kv::remove(base_price_key(&hex::encode(pool_ident)).as_str())?;
+    }
+    Ok(Ack)
+}
+
 fn trigger_sell(
     config: &StrategyConfig,
     now: u64,
@@ -64,11 +99,22 @@ fn trigger_sell(
         now + valid_for.as_millis() as u64,
     );
 
+    let Some(offer) = strategy
+        .usable_value(&config.network)
+        .map(|value| value.get(&config.give_token))
+    else {
+        info!(
+            "order {} can't cover its fees and deposit, not selling",
+            strategy.output
+        );
+        return Ok(Ack);
+    };
+
     let swap = Order::Swap {
         offer: (
             config.give_token.policy_id.clone(),
             config.give_token.asset_name.clone(),
-            asset_amount(&strategy.utxo, &config.give_token),
+            offer,
         ),
         min_received: (
             config.receive_token.policy_id.clone(),
@@ -77,7 +123,19 @@ fn trigger_sell(
         ),
     };
 
-    sundae_strategies::submit_execution(&config.network, &strategy.output, validity_range, swap)?;
+    let outcome = sundae_strategies::submit_execution_with(
+        &config.submitter,
+        &config.network,
+        &strategy.output,
+        validity_range,
+        swap,
+    )?;
+    if !outcome.is_accepted() {
+        warn!(
+            "execution of {} was not accepted: {:?}",
+            strategy.output, outcome
+        );
+    }
     Ok(Ack)
 }
 
@@ -86,6 +144,8 @@ fn main() -> Worker {
     balius_sdk::logging::init();
 
     Strategy::<StrategyConfig>::new()
+        .with_pool_filter(is_traded_pool)
         .on_new_pool_state(on_new_pool_state)
+        .on_strategy_spent(on_strategy_spent)
         .worker()
 }